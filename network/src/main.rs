@@ -2,30 +2,58 @@ extern crate image;
 extern crate ndarray;
 extern crate utils;
 
-use utils::dataset::load_mnist;
-use utils::network::{nn, train, train_one_by_one};
+use utils::dataset::{load_mnist, select, stratified_split, Normalize, TensorDataset};
+use utils::network::{nn, train_one_by_one, Checkpoint};
+use utils::random::set_seed;
 
 use std::path::Path;
 
 fn main() {
 
-    let paths: Vec<&Path> = vec![
+    let paths: [&Path; 4] = [
         "./mnist/train-images.idx3-ubyte".as_ref(),
         "./mnist/train-labels.idx1-ubyte".as_ref(),
         "./mnist/t10k-images.idx3-ubyte".as_ref(),
         "./mnist/t10k-labels.idx1-ubyte".as_ref(),
-
     ];
 
-    let ((x_train, y_train), (x_test, y_test)) = load_mnist(paths);
+    let ((x_train, y_train), (x_test, y_test)) = match load_mnist(paths) {
+        Ok(data) => data,
+        Err(error) => {
+            eprintln!("failed to load the dataset: {}", error);
+            std::process::exit(1)
+        }
+    };
     println!("Data loaded!");
 
+    // 10% of every digit of the training set is held out for validation, the test set is only used at the end
+    let (train_indices, validation_indices) = stratified_split(&y_train, 0.1, 1);
+    let (x_validation, y_validation) = select(&x_train, &y_train, &validation_indices);
+    let (x_train, y_train) = select(&x_train, &y_train, &train_indices);
+
+    // the same seed gives the same initial weights on every run
+    set_seed(1);
+
     let alpha = 0.001;
-    let mut network = create_network(alpha);
+    let mut checkpoint = Checkpoint::new(|| create_network(alpha));
     println!("Network created!");
 
+    // fitted on the training part only, the checkpoint applies it to the validation and test sets
+    let dataset = TensorDataset::new(x_train, y_train);
+    checkpoint.normalize = match Normalize::fit(&dataset) {
+        Ok(normalize) => Some(normalize),
+        Err(error) => {
+            eprintln!("failed to fit the normalization: {}", error);
+            std::process::exit(1)
+        }
+    };
+    let TensorDataset { inputs: x_train, labels: y_train } = dataset;
+
     println!("Starting training...");
-    train_one_by_one(&mut network, 5, x_test, y_test);
+    train_one_by_one(&mut checkpoint.network, 5, x_train, y_train, checkpoint.normalize.as_ref());
+
+    println!("Val-Acc: {:?}", checkpoint.predict(&x_validation, &y_validation));
+    println!("Test-Acc: {:?}", checkpoint.predict(&x_test, &y_test));
 
 }
 
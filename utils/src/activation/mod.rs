@@ -1,36 +1,57 @@
-use crate::propagation::Propagation;
+use crate::propagation::{Propagation, Gradients};
 use crate::utils;
-use utils::utils::{_relu, _softmax, relu_derivate};
+use utils::utils::{_relu, _relu_into, _softmax, relu_derivate};
 use ndarray::Array2;
+use crate::float::Float;
+use crate::workspace::{Scratch, resize};
+use rayon::prelude::*;
 
 pub struct Activation {
     pub end: usize
 }
 
-impl Propagation for Activation {
-    fn forward(&self, inputs: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
+impl<T: Float> Propagation<T> for Activation {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
         
         if self.end == 1 {
-            inputs.iter().map(|input| {
+            inputs.par_iter().map(|input| {
                 let mut z = input[0].to_owned();
                 _softmax(&mut z)
-            }).collect::<Vec<Vec<Array2<f32>>>>()
+            }).collect::<Vec<Vec<Array2<T>>>>()
         } else {
-            inputs.iter().map(|input| {
-                input.iter().map(|arr| _relu(arr)).collect::<Vec<Array2<f32>>>()
-            }).collect::<Vec<Vec<Array2<f32>>>>()
+            inputs.par_iter().map(|input| {
+                input.iter().map(|arr| _relu(arr)).collect::<Vec<Array2<T>>>()
+            }).collect::<Vec<Vec<Array2<T>>>>()
         }
     }
 
-    fn backward(&self, _: Vec<Vec<Array2<f32>>>, deltas: Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
+    fn forward_into(&self, inputs: &Vec<Vec<Array2<T>>>, output: &mut Vec<Vec<Array2<T>>>, _: &mut Scratch<T>) {
+        // relu writes into the arrays of output, softmax is small and allocates
         if self.end == 1 {
-            deltas
+            *output = self.forward(inputs);
         } else {
-            deltas.into_iter().map(|delta| {
-                delta.into_iter().map(|arr| relu_derivate(arr)).collect::<Vec<Array2<f32>>>()
-            }).collect::<Vec<Vec<Array2<f32>>>>()
+            resize(output, inputs.len(), inputs[0].len(), inputs[0][0].dim());
+            output.par_iter_mut().zip(inputs.par_iter()).for_each(|(out, input)| {
+                for (o, arr) in out.iter_mut().zip(input.iter()) {
+                    _relu_into(arr, o);
+                }
+            });
         }
     }
+
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        let samples = deltas.len();
+
+        let deltas = if self.end == 1 {
+            deltas
+        } else {
+            deltas.into_par_iter().zip(inputs.par_iter()).map(|(delta, input)| {
+                delta.into_iter().zip(input.iter()).map(|(arr, x)| relu_derivate(x, arr)).collect::<Vec<Array2<T>>>()
+            }).collect::<Vec<Vec<Array2<T>>>>()
+        };
+        (deltas, Gradients::empty(samples))
+    }
 }
 
 impl Activation {
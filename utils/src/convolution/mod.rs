@@ -1,225 +1,474 @@
-use crate::propagation::Propagation;
+use crate::propagation::{Propagation, Gradients};
 use crate::utils;
-use utils::{_convolution, _restore_with_channel, sum_nested_vector};
-use utils::utils::{cal_shape, _rotate};
+use utils::{_im2col_channels, _im2col_channels_into, _col2im_channels, _channels_to_matrix, _matrix_to_channels, _restore_with_channel, _transposed_convolution, _transposed_im2col};
+use utils::utils::{cal_shape, cal_backward_shape};
 
-use ndarray::{Array, Array2, Axis};
-use ndarray_rand::rand_distr::StandardNormal;
-use ndarray_rand::RandomExt;
+use ndarray::{s, stack, Array, Array2, Axis};
+use crate::float::Float;
+use crate::linalg::{dot, gemm};
+use crate::workspace::{Scratch, resize, resize_matrix};
+use crate::random;
 
-use std::cell::RefCell;
+use rayon::prelude::*;
+use std::sync::RwLock;
 use std::fmt::{Formatter, Display, Result};
 
-// transposed_filter will change after updating filter
-// therefore, the method to update the filter must re-calculate the transposed filter
-pub struct Conv2D {
-    pub prev: usize,
+// the shape of a convolution, Conv3D::new(ConvConfig::new(in_channel, out_channel, stride, padding, prev_width, filter_width), alpha)
+// dilation and groups default to 1 and boundary to 0, change them with the methods of the same name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConvConfig {
+    pub in_channel: usize,
+    pub out_channel: usize,
+    pub stride: usize,
+    pub padding: usize,
+    pub prev_width: usize,
     pub filter_width: usize,
-    pub filter: RefCell<Array2<f32>>,
-    pub bias: RefCell<Array2<f32>>,
+    pub dilation: usize,
+    pub groups: usize,
+    pub boundary: usize
+}
+
+impl ConvConfig {
+    pub fn new(in_channel: usize, out_channel: usize, stride: usize, padding: usize, prev_width: usize, filter_width: usize) -> ConvConfig {
+        ConvConfig { in_channel, out_channel, stride, padding, prev_width, filter_width, dilation: 1, groups: 1, boundary: 0 }
+    }
+
+    pub fn dilation(self, dilation: usize) -> ConvConfig {
+        ConvConfig { dilation, ..self }
+    }
+
+    pub fn groups(self, groups: usize) -> ConvConfig {
+        ConvConfig { groups, ..self }
+    }
+
+    // boundary == 1 when the next layer is a FullLayer, its deltas come back flattened
+    pub fn boundary(self, boundary: usize) -> ConvConfig {
+        ConvConfig { boundary, ..self }
+    }
+}
+
+// dilation inserts (dilation - 1) gaps between the filter elements, dilation == 1 is the normal convolution
+// every output channel only sees the in_channel / groups input channels of its group
+// groups == in_channel == out_channel is the depthwise convolution
+pub struct Conv3D<T = f32> {
+    pub in_channel: usize,
+    pub out_channel: usize,
     pub stride: usize,
     pub padding: usize,
-    pub alpha: f32,
-    pub transposed_filter: RefCell<Array2<f32>>
+    pub prev_width: usize,
+    pub output_width: usize,
+    pub filter_width: usize,
+    pub dilation: usize,
+    pub groups: usize,
+    pub alpha: T,
+    pub boundary: usize,
+    // weights [out_channel, in_channel / groups * filter_width * filter_width]
+    // the row of an output channel holds the filters of its input channels one after another
+    pub weights: RwLock<Array2<T>>,
+    // bias [out_channel, 1]
+    pub bias: RwLock<Array2<T>>
+}
+
+impl<T: Float> Propagation<T> for Conv3D<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        // one im2col for the whole batch and one GEMM for every group
+        // cols [in_channel * f * f, sample * output_width * output_width]
+        let pixels = self.output_width * self.output_width;
+        let cols = self.im2col(inputs);
+        let weights = self.weights.read().unwrap();
+        let (in_size, out_size) = self.group_size();
+
+        let mut outputs: Array2<T> = Array::zeros((self.out_channel, cols.shape()[1]));
+        for g in 0..self.groups {
+            outputs.slice_mut(s![g * out_size..(g + 1) * out_size, ..]).assign(
+                &dot(&weights.slice(s![g * out_size..(g + 1) * out_size, ..]),
+                    &cols.slice(s![g * in_size..(g + 1) * in_size, ..]))
+            );
+        }
+        outputs = outputs + &*self.bias.read().unwrap(); // [out_channel, sample * output_width * output_width]
+
+        (0..inputs.len()).into_par_iter().map(|sample| {
+            _matrix_to_channels(outputs.slice(s![.., sample * pixels..(sample + 1) * pixels]), self.output_width)
+        }).collect::<Vec<Vec<Array2<T>>>>()
+    }
+
+    fn forward_into(&self, inputs: &Vec<Vec<Array2<T>>>, output: &mut Vec<Vec<Array2<T>>>, scratch: &mut Scratch<T>) {
+        // same as forward, scratch.cols holds the im2col matrix and scratch.product the GEMM output
+        let samples = inputs.len();
+        let pixels = self.output_width * self.output_width;
+        let (in_size, out_size) = self.group_size();
+
+        resize_matrix(&mut scratch.cols, (self.in_channel * self.filter_width * self.filter_width, samples * pixels));
+        let cols = scratch.cols.axis_chunks_iter_mut(Axis(1), pixels).collect::<Vec<_>>();
+        cols.into_par_iter().zip(inputs.par_iter()).for_each(|(col, input)| {
+            _im2col_channels_into(input, self.filter_width, self.stride, self.padding, self.dilation, col)
+        });
+
+        resize_matrix(&mut scratch.product, (self.out_channel, samples * pixels));
+        let weights = self.weights.read().unwrap();
+        for g in 0..self.groups {
+            gemm(
+                T::one(),
+                &weights.slice(s![g * out_size..(g + 1) * out_size, ..]),
+                &scratch.cols.slice(s![g * in_size..(g + 1) * in_size, ..]),
+                T::zero(),
+                &mut scratch.product.slice_mut(s![g * out_size..(g + 1) * out_size, ..])
+            );
+        }
+        scratch.product += &*self.bias.read().unwrap();
+
+        resize(output, samples, self.out_channel, (self.output_width, self.output_width));
+        let product = &scratch.product;
+        output.par_iter_mut().enumerate().for_each(|(sample, channels)| {
+            for (channel, arr) in channels.iter_mut().enumerate() {
+                arr.assign(&product.slice(s![channel, sample * pixels..(sample + 1) * pixels])
+                    .into_shape((self.output_width, self.output_width)).unwrap());
+            }
+        });
+    }
+
+    
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>) 
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        // next_deltas : [sample, out_channel, output_width, output_width]
+        // inputs: [sample, in_channel, input_width, input_width]
+        // output: [sample, in_channel, input_width, input_width]
+        let samples = next_deltas.len();
+        let pixels = self.output_width * self.output_width;
+        let cols = self.im2col(inputs);
+        let (in_size, out_size) = self.group_size();
+
+        // if conv layer is connected with full layer, must convert delta [sample, channel * width * width] to [sample, channel, width, width]
+        let deltas = next_deltas.into_par_iter().map(|delta| {
+            let delta_with_channel = _restore_with_channel(delta, self.out_channel,
+                self.prev_width, self.filter_width, self.stride, self.padding, self.dilation, self.boundary);
+            _channels_to_matrix(&delta_with_channel)
+        }).collect::<Vec<Array2<T>>>();
+        let views = deltas.iter().map(|delta| delta.view()).collect::<Vec<_>>();
+        let deltas = stack(Axis(1), &views).unwrap(); // [out_channel, sample * output_width * output_width]
+
+        let mut derivate_weights: Array2<T> = Array::zeros(self.weights.read().unwrap().raw_dim());
+        let mut derivate_cols: Array2<T> = Array::zeros(cols.raw_dim());
+        {
+            let weights = self.weights.read().unwrap();
+            for g in 0..self.groups {
+                let delta = deltas.slice(s![g * out_size..(g + 1) * out_size, ..]);
+
+                derivate_weights.slice_mut(s![g * out_size..(g + 1) * out_size, ..]).assign(
+                    &dot(&delta, &cols.slice(s![g * in_size..(g + 1) * in_size, ..]).t())
+                );
+                derivate_cols.slice_mut(s![g * in_size..(g + 1) * in_size, ..]).assign(
+                    &dot(&weights.slice(s![g * out_size..(g + 1) * out_size, ..]).t(), &delta)
+                );
+            }
+        }
+        let derivate_bias = deltas.sum_axis(Axis(1)).insert_axis(Axis(1));
+
+        let deltas = (0..samples).into_par_iter().map(|sample| {
+            _col2im_channels(derivate_cols.slice(s![.., sample * pixels..(sample + 1) * pixels]),
+                self.prev_width, self.filter_width, self.stride, self.padding, self.dilation)
+        }).collect::<Vec<Vec<Array2<T>>>>();
+
+        (deltas, Gradients::new(vec![derivate_weights, derivate_bias], samples))
+    }
+
+    fn apply(&self, gradients: &Gradients<T>) {
+        self.update(&gradients.derivates[0], &gradients.derivates[1], gradients.samples);
+    }
+
+    fn parameters(&self) -> Vec<Array2<T>> {
+        vec![self.weights.read().unwrap().clone(), self.bias.read().unwrap().clone()]
+    }
+
+    fn set_parameters(&self, parameters: &[Array2<T>]) {
+        *self.weights.write().unwrap() = parameters[0].clone();
+        *self.bias.write().unwrap() = parameters[1].clone();
+    }
 }
 
-impl Conv2D {
-    pub fn new(prev_width: usize, width: usize, padding: usize, stride: usize, alpha: f32) -> Conv2D {
-        let (filter, bias) = Conv2D::initialization(prev_width, width, padding, stride);
-        let transposed_filter = _rotate(&filter, 2);
-        Conv2D {
-            prev: prev_width,
-            filter_width: width,
-            filter: RefCell::new(filter),
-            bias: RefCell::new(bias),
+impl<T: Float> Conv3D<T> {
+    pub fn new(config: ConvConfig, alpha: T) -> Conv3D<T> {
+        let ConvConfig { in_channel, out_channel, stride, padding, prev_width, filter_width, dilation, groups, boundary } = config;
+        assert!(groups > 0, "a convolution needs at least 1 group");
+        assert!(in_channel.is_multiple_of(groups) && out_channel.is_multiple_of(groups), "channels must be divisible by groups");
+
+        let output_width = cal_shape(prev_width, filter_width, stride, padding, dilation);
+        let (weights, bias) = Conv3D::initialization(in_channel / groups, out_channel, filter_width);
+
+        Conv3D {
+            in_channel,
+            out_channel,
             stride,
             padding,
+            prev_width,
+            output_width,
+            filter_width,
+            dilation,
+            groups,
             alpha,
-            transposed_filter: RefCell::new(transposed_filter)
+            boundary,
+            weights: RwLock::new(weights),
+            bias: RwLock::new(bias)
         }
     }
 
-    fn initialization(prev_width: usize, width: usize, stride: usize,padding: usize) 
-    -> (Array2<f32>, Array2<f32>) {
+    fn initialization(in_per_group: usize, out_channel: usize, filter_width: usize)
+    -> (Array2<T>, Array2<T>) {
         (
-            Array::random((width, width), StandardNormal) * 0.05,
-            Array::zeros((cal_shape(prev_width, width, stride, padding), 1))
+            random::normal((out_channel, in_per_group * filter_width * filter_width)) * T::from_f32(0.05),
+            Array::zeros((out_channel, 1))
         )
     }
+
 }
 
-impl Conv2D {
-    pub fn forward(&self, input: &Array2<f32>) -> Array2<f32> {
-        _convolution(&*self.filter.borrow(), input, self.stride, self.padding) + &*self.bias.borrow()
+impl<T: Float> Conv3D<T> {
+
+    fn im2col(&self, inputs: &Vec<Vec<Array2<T>>>) -> Array2<T> {
+        // [in_channel * f * f, sample * output_width * output_width]
+        let cols = inputs.par_iter().map(|input| {
+            _im2col_channels(input, self.filter_width, self.stride, self.padding, self.dilation)
+        }).collect::<Vec<Array2<T>>>();
+        let views = cols.iter().map(|col| col.view()).collect::<Vec<_>>();
+
+        stack(Axis(1), &views).unwrap()
     }
 
-    pub fn cal_delta(&self, next_delta: &Array2<f32>) -> Array2<f32> {
-        _convolution(&*self.transposed_filter.borrow(), next_delta, self.stride, self.padding)
+    fn group_size(&self) -> (usize, usize) {
+        // (rows of cols, rows of weights) in every group
+        (
+            self.in_channel / self.groups * self.filter_width * self.filter_width,
+            self.out_channel / self.groups
+        )
     }
 
-    pub fn update(&self, derivate_bias: &Array2<f32>, derivate_filter: &Array2<f32>) {
-        let cloned_bias = self.bias.borrow().clone();
-        let cloned_filter = self.filter.borrow().clone();
-        let shape = cloned_bias.shape()[0];
+    fn update(&self, derivate_weights: &Array2<T>, derivate_bias: &Array2<T>, samples: usize) {
+        let cloned_weights = self.weights.read().unwrap().clone();
+        let cloned_bias = self.bias.read().unwrap().clone();
 
-        *self.filter.borrow_mut() = cloned_filter - self.alpha * derivate_filter;
-        *self.bias.borrow_mut() = cloned_bias - self.alpha * derivate_bias.sum_axis(Axis(1)).into_shape((shape, 1)).unwrap() / self.prev as f32;
-        *self.transposed_filter.borrow_mut() = _rotate(&*self.filter.borrow(), 2);
+        *self.weights.write().unwrap() = cloned_weights - derivate_weights * self.alpha / T::from_usize(samples);
+        *self.bias.write().unwrap() = cloned_bias - derivate_bias * self.alpha / T::from_usize(samples);
     }
-
 }
 
-impl Display for Conv2D {
+impl<T: Float> Display for Conv3D<T> {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "Conv2D filter {}", self.filter.borrow())
+        write!(f, "Conv3D [0][0] filter {}", self.weights.read().unwrap().slice(s![0, ..self.filter_width * self.filter_width]))
     }
 }
 
-pub struct Conv3D {
+// depthwise separable convolution
+// depthwise: every input channel is convolved with its own filter (groups == in_channel)
+// pointwise: 1x1 convolution mixing the channels into out_channel
+pub struct SeparableConv<T = f32> {
+    pub depthwise: Conv3D<T>,
+    pub pointwise: Conv3D<T>
+}
+
+impl<T: Float> Propagation<T> for SeparableConv<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        self.pointwise.forward(&self.depthwise.forward(inputs))
+    }
+
+    fn infer(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        self.pointwise.infer(&self.depthwise.infer(inputs))
+    }
+
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        // derivates: [depthwise weights, depthwise bias, pointwise weights, pointwise bias]
+        // the output of depthwise (the input of pointwise) is computed again from inputs
+        let hidden = self.depthwise.forward(inputs);
+        let (deltas, pointwise) = self.pointwise.gradients(&hidden, next_deltas);
+        let (deltas, mut depthwise) = self.depthwise.gradients(inputs, deltas);
+
+        depthwise.derivates.extend(pointwise.derivates);
+        (deltas, depthwise)
+    }
+
+    fn apply(&self, gradients: &Gradients<T>) {
+        let (depthwise, pointwise) = gradients.derivates.split_at(2);
+        self.depthwise.apply(&Gradients::new(depthwise.to_vec(), gradients.samples));
+        self.pointwise.apply(&Gradients::new(pointwise.to_vec(), gradients.samples));
+    }
+
+    fn parameters(&self) -> Vec<Array2<T>> {
+        let mut parameters = self.depthwise.parameters();
+        parameters.extend(self.pointwise.parameters());
+        parameters
+    }
+
+    fn set_parameters(&self, parameters: &[Array2<T>]) {
+        let (depthwise, pointwise) = parameters.split_at(2);
+        self.depthwise.set_parameters(depthwise);
+        self.pointwise.set_parameters(pointwise);
+    }
+}
+
+impl<T: Float> SeparableConv<T> {
+    // config.groups is ignored, the depthwise part has in_channel groups and the pointwise part one
+    pub fn new(config: ConvConfig, alpha: T) -> SeparableConv<T> {
+        let depthwise = Conv3D::new(ConvConfig {
+            out_channel: config.in_channel,
+            groups: config.in_channel,
+            boundary: 0,
+            ..config
+        }, alpha);
+        let pointwise = Conv3D::new(ConvConfig::new(config.in_channel, config.out_channel, 1, 0, depthwise.output_width, 1)
+            .boundary(config.boundary), alpha);
+
+        SeparableConv {
+            depthwise,
+            pointwise
+        }
+    }
+}
+
+// transposed convolution, increases the spatial size of the input
+// output_width = (prev_width - 1) * stride - 2 * padding + filter_width + output_padding
+// output_padding must be smaller than stride
+pub struct ConvTranspose2D<T = f32> {
     pub in_channel: usize,
     pub out_channel: usize,
     pub stride: usize,
     pub padding: usize,
+    pub output_padding: usize,
     pub prev_width: usize,
     pub output_width: usize,
     pub filter_width: usize,
-    pub alpha: f32,
-    pub boundary: usize,
-    pub conv2d: RefCell<Vec<Vec<Conv2D>>>
+    pub alpha: T,
+    // filters [in_channel, out_channel, filter_width, filter_width]
+    pub filters: RwLock<Vec<Vec<Array2<T>>>>,
+    // bias [out_channel, 1]
+    pub bias: RwLock<Array2<T>>
 }
 
-impl Propagation for Conv3D {
-    fn forward(&self, inputs: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
-        inputs.iter().map(|input| self._forward(input)).collect::<Vec<Vec<Array2<f32>>>>()
+impl<T: Float> Propagation<T> for ConvTranspose2D<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        inputs.par_iter().map(|input| self._forward(input)).collect::<Vec<Vec<Array2<T>>>>()
     }
 
-    
-    fn backward(&self, inputs: Vec<Vec<Array2<f32>>>, next_deltas: Vec<Vec<Array2<f32>>>) 
-    -> Vec<Vec<Array2<f32>>> {
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
         // next_deltas : [sample, out_channel, output_width, output_width]
-        // inputs: [sample, in_channel, input_width, input_width]
-        // output: [sample, in_channel, input_width, input_width]
+        // inputs: [sample, in_channel, prev_width, prev_width]
+        // derivates: [in_channel * out_channel filters (filters[i][out] at i * out_channel + out), bias]
+        // output: [sample, in_channel, prev_width, prev_width]
         let samples = next_deltas.len();
+        let filter_size = self.filter_width * self.filter_width;
 
-        let derivate_filters: Vec<Vec<Array2<f32>>> = next_deltas.iter().zip(inputs.into_iter()).fold(
-            (0..self.out_channel).map(|_| {
-                (0..self.in_channel).map(|_| {
-                    Array::zeros((self.filter_width, self.filter_width))
-                }).collect::<Vec<Array2<f32>>>()
-            }).collect::<Vec<Vec<Array2<f32>>>>(),
-            |acc, (delta, input)| {
-                self.cal_derivate_filters(delta, input).into_iter().zip(acc.into_iter()).map(|(a, b)| {
-                    sum_nested_vector(a, b)
-                }).collect::<Vec<Vec<Array2<f32>>>>()
-            }
-        ); // [out_channel, in_channel, filter_width, filter_width]
-
-        let average_deltas =  next_deltas.iter().fold(
-            (0..self.out_channel).map(
-                |_| 
-                Array::zeros((self.output_width, self.output_width)))
-                .collect::<Vec<Array2<f32>>>(),
-                |acc, delta| {
-                    let average_delta: Vec<Array2<f32>> = delta.iter().map(
-                        |ele| ele / samples as f32
-                    ).collect();
-                    sum_nested_vector(acc, average_delta)
-            }
-        ); // [out_channel, output_width, output_width]
+        let mut derivate_filters: Vec<Vec<Array2<T>>> = (0..self.in_channel).map(|_| {
+            (0..self.out_channel).map(|_| Array::zeros((self.filter_width, self.filter_width))).collect()
+        }).collect();
+        let mut derivate_bias: Array2<T> = Array::zeros((self.out_channel, 1));
 
-        for out in 0..self.out_channel {
-            for i in 0..self.in_channel {
-                self.conv2d.borrow_mut()[out][i].update(&average_deltas[out], &derivate_filters[out][i]);
+        let deltas = next_deltas.iter().zip(inputs.iter()).map(|(delta, input)| {
+            // cols [out_channel, prev_width * prev_width, f * f]
+            let cols: Vec<Array2<T>> = delta.iter().map(|d| {
+                _transposed_im2col(d, self.prev_width, self.filter_width, self.stride, self.padding)
+            }).collect();
+
+            for (out, d) in delta.iter().enumerate() {
+                derivate_bias[[out, 0]] += d.sum();
             }
-        }
 
-        next_deltas.into_iter().map(|delta| {
-            self.cal_delta(delta)
-        }).collect::<Vec<Vec<Array2<f32>>>>()
+            let filters = self.filters.read().unwrap();
+            (0..self.in_channel).map(|i| {
+                let flattened_input = input[i].to_owned().into_shape((1, self.prev_width * self.prev_width)).unwrap();
+
+                (0..self.out_channel).fold(Array::zeros((self.prev_width, self.prev_width)), |acc, out| {
+                    derivate_filters[i][out] = &derivate_filters[i][out] + &flattened_input.dot(&cols[out])
+                        .into_shape((self.filter_width, self.filter_width)).unwrap();
+
+                    let flattened_filter = filters[i][out].to_owned().into_shape((filter_size, 1)).unwrap();
+                    acc + cols[out].dot(&flattened_filter).into_shape((self.prev_width, self.prev_width)).unwrap()
+                })
+            }).collect::<Vec<Array2<T>>>()
+        }).collect::<Vec<Vec<Array2<T>>>>();
+
+        let mut derivates = derivate_filters.into_iter().flatten().collect::<Vec<Array2<T>>>();
+        derivates.push(derivate_bias);
+
+        (deltas, Gradients::new(derivates, samples))
+    }
+
+    fn apply(&self, gradients: &Gradients<T>) {
+        let (derivate_filters, derivate_bias) = gradients.derivates.split_at(self.in_channel * self.out_channel);
+        self.update(derivate_filters, &derivate_bias[0], gradients.samples);
+    }
+
+    fn parameters(&self) -> Vec<Array2<T>> {
+        let mut parameters = self.filters.read().unwrap().iter().flatten().cloned().collect::<Vec<Array2<T>>>();
+        parameters.push(self.bias.read().unwrap().clone());
+        parameters
+    }
+
+    fn set_parameters(&self, parameters: &[Array2<T>]) {
+        for (filter, parameter) in self.filters.write().unwrap().iter_mut().flatten().zip(parameters.iter()) {
+            *filter = parameter.clone();
+        }
+        *self.bias.write().unwrap() = parameters[self.in_channel * self.out_channel].clone();
     }
 }
 
-impl Conv3D {
+impl<T: Float> ConvTranspose2D<T> {
     pub fn new(
         in_channel: usize,
         out_channel: usize,
         stride: usize,
         padding: usize,
+        output_padding: usize,
         prev_width: usize,
         filter_width: usize,
-        alpha: f32,
-        boundary: usize
-    ) -> Conv3D {
-        // conv2d: (out_channel, in_channel)
-        let conv2d: Vec<Vec<Conv2D>> = (0..out_channel).map(
-            |_| (0..in_channel).map(
-                |_| Conv2D::new(prev_width, filter_width, padding, stride, alpha)
-            ).collect::<Vec<Conv2D>>()
-        ).collect();
-        let output_width = cal_shape(prev_width, filter_width, stride, padding);
+        alpha: T
+    ) -> ConvTranspose2D<T> {
+        assert!(output_padding < stride, "output_padding must be smaller than stride");
 
-        Conv3D {
+        let filters: Vec<Vec<Array2<T>>> = (0..in_channel).map(|_| {
+            (0..out_channel).map(|_| {
+                random::normal((filter_width, filter_width)) * T::from_f32(0.05)
+            }).collect::<Vec<Array2<T>>>()
+        }).collect();
+        let output_width = cal_backward_shape(prev_width, filter_width, stride, padding) + output_padding;
+
+        ConvTranspose2D {
             in_channel,
             out_channel,
             stride,
             padding,
+            output_padding,
             prev_width,
             output_width,
             filter_width,
             alpha,
-            boundary,
-            conv2d: RefCell::new(conv2d)
+            filters: RwLock::new(filters),
+            bias: RwLock::new(Array::zeros((out_channel, 1)))
         }
     }
-
 }
 
-impl Conv3D {
+impl<T: Float> ConvTranspose2D<T> {
 
-    fn _forward(&self, input: &Vec<Array2<f32>>) -> Vec<Array2<f32>> {
+    fn _forward(&self, input: &Vec<Array2<T>>) -> Vec<Array2<T>> {
         // input.len() == in_channel
         // output.len() == out_channel
-        (0..self.out_channel).map(
-            |out_index| {
-                let outputs: Vec<Array2<f32>> = input.iter().zip(self.conv2d.borrow()[out_index].iter()).map(
-                    |(data, conv)| conv.forward(data)
-                ).collect();
-                let summary = Array::zeros((self.output_width, self.output_width));
-                outputs.into_iter().fold(summary, |acc, output| acc + output)
-            }
-        ).collect::<Vec<Array2<f32>>>()
-    }
-
-    fn cal_delta(&self, next_delta: Vec<Array2<f32>>) -> Vec<Array2<f32>> {
-        // next_delta.len() == out_channel
-        // output.len() == in_channel
-        // when backward 
-        // if conv layer is connected with full layer, must convert delta [sample, channel * width * width] to [sample, channel, width, width] 
-        let delta_with_channel = _restore_with_channel(next_delta, self.out_channel,
-            self.prev_width, self.filter_width, self.stride, self.padding, self.boundary);
-        
-        (0..self.in_channel).map(|in_index| {
-            let outputs: Vec<Array2<f32>> = delta_with_channel.iter().enumerate().map(|(out_index, data)| {
-                self.conv2d.borrow()[out_index][in_index].cal_delta(data)
-            }).collect();
-            let summary = Array::zeros((self.prev_width, self.prev_width));
-            outputs.into_iter().fold(summary, |acc, output| acc + output)
-        }).collect::<Vec<Array2<f32>>>()
-    }
+        let filters = self.filters.read().unwrap();
+        let bias = self.bias.read().unwrap();
 
-    fn cal_derivate_filters(&self, delta: &Vec<Array2<f32>>, input: Vec<Array2<f32>>) -> Vec<Vec<Array2<f32>>> {
-        // delta [out_channel, width, width]
-        // input [in_channel, width, width]
-        (0..self.out_channel).map(|out_index| {
-            (0..self.in_channel).map(|in_index| {
-                _convolution(&delta[out_index], &input[in_index], self.stride, self.padding)
-            }).collect::<Vec<Array2<f32>>>()
-        }).collect::<Vec<Vec<Array2<f32>>>>()
+        (0..self.out_channel).map(|out| {
+            let summary = Array::from_elem((self.output_width, self.output_width), bias[[out, 0]]);
+            input.iter().enumerate().fold(summary, |acc, (i, data)| {
+                acc + _transposed_convolution(&filters[i][out], data, self.stride, self.padding, self.output_padding)
+            })
+        }).collect::<Vec<Array2<T>>>()
     }
-}
 
-impl Display for Conv3D {
-    fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "Conv3D [0][0] filter {}", self.conv2d.borrow()[0][0])
+    fn update(&self, derivate_filters: &[Array2<T>], derivate_bias: &Array2<T>, samples: usize) {
+        let cloned_bias = self.bias.read().unwrap().clone();
+
+        for (filter, derivate) in self.filters.write().unwrap().iter_mut().flatten().zip(derivate_filters.iter()) {
+            filter.scaled_add(-self.alpha / T::from_usize(samples), derivate);
+        }
+        *self.bias.write().unwrap() = cloned_bias - derivate_bias * self.alpha / T::from_usize(samples);
     }
-}
\ No newline at end of file
+}
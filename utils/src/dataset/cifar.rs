@@ -0,0 +1,78 @@
+use ndarray::{Array2, ArrayView1, Axis};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::dataset::{open, DatasetError, Split};
+use crate::utils::one_hot;
+
+// CIFAR-10 (cifar-10-batches-bin) and CIFAR-100 (cifar-100-binary) binary versions
+// one record per image: the label bytes, then 3072 pixels, 1024 red, 1024 green and 1024 blue in row-major 32x32
+// CIFAR-10 has 1 label byte, CIFAR-100 2: the coarse label (20 superclasses) then the fine label (100 classes)
+const SIDE: usize = 32;
+const CHANNELS: usize = 3;
+const PIXELS: usize = CHANNELS * SIDE * SIDE;
+
+// images, coarse labels, fine labels
+pub type Split100 = (Vec<Vec<Array2<f32>>>, Array2<f32>, Array2<f32>);
+
+// images and the label bytes [sample, label bytes]
+type Records = (Vec<Vec<Array2<f32>>>, Array2<u8>);
+
+pub fn load_cifar10<P: AsRef<Path>>(dir: P) -> Result<(Split, Split), DatasetError> {
+    // dir: the extracted cifar-10-batches-bin, data_batch_1.bin ... data_batch_5.bin and test_batch.bin
+    let dir = dir.as_ref();
+    let train = (1..=5).map(|i| dir.join(format!("data_batch_{}.bin", i))).collect::<Vec<PathBuf>>();
+    let test = vec![dir.join("test_batch.bin")];
+    Ok((load_cifar10_batches(&train)?, load_cifar10_batches(&test)?))
+}
+
+pub fn load_cifar10_batches<P: AsRef<Path>>(paths: &[P]) -> Result<Split, DatasetError> {
+    // the batches concatenated in order, images [sample, 3, 32, 32] in [0, 1] and one-hot labels [sample, 10]
+    let (images, labels) = read_batches(paths, 1)?;
+    Ok((images, to_one_hot(labels.column(0), 10)?))
+}
+
+pub fn load_cifar100<P: AsRef<Path>>(dir: P) -> Result<(Split100, Split100), DatasetError> {
+    // dir: the extracted cifar-100-binary, train.bin and test.bin
+    let dir = dir.as_ref();
+    Ok((load_cifar100_batches(&[dir.join("train.bin")])?, load_cifar100_batches(&[dir.join("test.bin")])?))
+}
+
+pub fn load_cifar100_batches<P: AsRef<Path>>(paths: &[P]) -> Result<Split100, DatasetError> {
+    // images [sample, 3, 32, 32] in [0, 1], one-hot coarse labels [sample, 20] and fine labels [sample, 100]
+    let (images, labels) = read_batches(paths, 2)?;
+    Ok((images, to_one_hot(labels.column(0), 20)?, to_one_hot(labels.column(1), 100)?))
+}
+
+fn read_batches<P: AsRef<Path>>(paths: &[P], label_bytes: usize) -> Result<Records, DatasetError> {
+    let record = label_bytes + PIXELS;
+    let mut images = vec![];
+    let mut labels = vec![];
+
+    for path in paths {
+        let mut buf = vec![];
+        open(path)?.read_to_end(&mut buf)?;
+        if buf.len() % record != 0 {
+            return Err(DatasetError::Truncated { expected: (buf.len() / record + 1) * record, found: buf.len() })
+        }
+
+        for bytes in buf.chunks_exact(record) {
+            labels.extend_from_slice(&bytes[..label_bytes]);
+            images.push(bytes[label_bytes..].chunks_exact(SIDE * SIDE).map(|channel| {
+                Array2::from_shape_fn((SIDE, SIDE), |(i, j)| channel[i * SIDE + j] as f32 / 255.)
+            }).collect::<Vec<Array2<f32>>>());
+        }
+    }
+
+    let labels = Array2::from_shape_vec((images.len(), label_bytes), labels).unwrap();
+    Ok((images, labels))
+}
+
+fn to_one_hot(labels: ArrayView1<u8>, classes: usize) -> Result<Array2<f32>, DatasetError> {
+    // one_hot would turn an unknown label into a row of zeros
+    if let Some(&label) = labels.iter().find(|&&label| label as usize >= classes) {
+        return Err(DatasetError::LabelOutOfRange { label: label as usize, classes })
+    }
+    let labels = labels.mapv(|label| label as f32).insert_axis(Axis(1));
+    Ok(one_hot(labels, classes))
+}
@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use rand::distributions::WeightedError;
+
+// everything that can go wrong while loading a dataset
+// Io: the file cannot be opened or read
+// Image: the image file cannot be decoded
+// Csv: the CSV file cannot be parsed (rows of different lengths, invalid UTF-8)
+// BadMagic: the header is not the one of the format (or an unknown data type)
+// Truncated: the file ends before the size given in the header, expected and found in bytes
+// ShapeMismatch: the data does not have the shape the loader needs
+// LabelCountMismatch: not one label per sample
+// LabelOutOfRange: a label is not one of the classes of the dataset
+// MissingColumn: the table has no column of this name
+// InvalidValue: a value that is not a number in a numeric column, or a class/category unseen when fitting
+// WeightCountMismatch: a Weighted sampler without one weight per sample
+// InvalidWeights: the weights of a Weighted sampler are negative, not finite or all 0
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(io::Error),
+    Image(PathBuf, image::ImageError),
+    Csv(csv::Error),
+    BadMagic([u8; 4]),
+    Truncated { expected: usize, found: usize },
+    ShapeMismatch { expected: String, found: Vec<usize> },
+    LabelCountMismatch { samples: usize, labels: usize },
+    LabelOutOfRange { label: usize, classes: usize },
+    MissingColumn(String),
+    InvalidValue { column: String, value: String },
+    WeightCountMismatch { samples: usize, weights: usize },
+    InvalidWeights(WeightedError)
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatasetError::Io(error) => write!(f, "{}", error),
+            DatasetError::Image(path, error) => write!(f, "{}: {}", path.display(), error),
+            DatasetError::Csv(error) => write!(f, "{}", error),
+            DatasetError::BadMagic(magic) => write!(f, "invalid magic number {:02x?}", magic),
+            DatasetError::Truncated { expected, found } => {
+                write!(f, "the data file is truncated, expect {} bytes, got {}", expected, found)
+            },
+            DatasetError::ShapeMismatch { expected, found } => {
+                write!(f, "invalid shape, expect {}, got {:?}", expected, found)
+            },
+            DatasetError::LabelCountMismatch { samples, labels } => {
+                write!(f, "{} samples but {} labels", samples, labels)
+            },
+            DatasetError::LabelOutOfRange { label, classes } => {
+                write!(f, "invalid label {}, expect less than {} classes", label, classes)
+            },
+            DatasetError::MissingColumn(name) => write!(f, "no column named {:?}", name),
+            DatasetError::InvalidValue { column, value } => {
+                write!(f, "invalid value {:?} in column {:?}", value, column)
+            },
+            DatasetError::WeightCountMismatch { samples, weights } => {
+                write!(f, "{} samples but {} weights", samples, weights)
+            },
+            DatasetError::InvalidWeights(error) => write!(f, "invalid sampler weights: {}", error),
+        }
+    }
+}
+
+impl Error for DatasetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DatasetError::Io(error) => Some(error),
+            DatasetError::Image(_, error) => Some(error),
+            DatasetError::Csv(error) => Some(error),
+            DatasetError::InvalidWeights(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DatasetError {
+    fn from(error: io::Error) -> DatasetError {
+        DatasetError::Io(error)
+    }
+}
+
+impl From<csv::Error> for DatasetError {
+    fn from(error: csv::Error) -> DatasetError {
+        DatasetError::Csv(error)
+    }
+}
+
+impl From<WeightedError> for DatasetError {
+    fn from(error: WeightedError) -> DatasetError {
+        DatasetError::InvalidWeights(error)
+    }
+}
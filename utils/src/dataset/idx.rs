@@ -0,0 +1,124 @@
+use ndarray::{ArrayD, IxDyn};
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::dataset::{open, DatasetError};
+
+// IDX files (MNIST, Fashion-MNIST, EMNIST, KMNIST, ...)
+// magic number: [0, 0, data type, number of dimensions]
+// then one big-endian u32 per dimension and the big-endian data in row-major order
+// data type: 0x08 u8, 0x09 i8, 0x0B i16, 0x0C i32, 0x0D f32, 0x0E f64
+pub enum Idx {
+    U8(ArrayD<u8>),
+    I8(ArrayD<i8>),
+    I16(ArrayD<i16>),
+    I32(ArrayD<i32>),
+    F32(ArrayD<f32>),
+    F64(ArrayD<f64>)
+}
+
+impl Idx {
+    pub fn shape(&self) -> &[usize] {
+        match self {
+            Idx::U8(data) => data.shape(),
+            Idx::I8(data) => data.shape(),
+            Idx::I16(data) => data.shape(),
+            Idx::I32(data) => data.shape(),
+            Idx::F32(data) => data.shape(),
+            Idx::F64(data) => data.shape(),
+        }
+    }
+
+    pub fn dtype(&self) -> &'static str {
+        match self {
+            Idx::U8(_) => "u8",
+            Idx::I8(_) => "i8",
+            Idx::I16(_) => "i16",
+            Idx::I32(_) => "i32",
+            Idx::F32(_) => "f32",
+            Idx::F64(_) => "f64",
+        }
+    }
+
+    pub fn to_f32(&self) -> ArrayD<f32> {
+        // the values as they are, without scaling
+        match self {
+            Idx::U8(data) => data.mapv(|x| x as f32),
+            Idx::I8(data) => data.mapv(|x| x as f32),
+            Idx::I16(data) => data.mapv(|x| x as f32),
+            Idx::I32(data) => data.mapv(|x| x as f32),
+            Idx::F32(data) => data.to_owned(),
+            Idx::F64(data) => data.mapv(|x| x as f32),
+        }
+    }
+}
+
+pub fn load_idx<P: AsRef<Path>>(path: P) -> Result<Idx, DatasetError> {
+    // raw or gzip-compressed (train-images-idx3-ubyte.gz as distributed)
+    read_idx(&mut open(path)?)
+}
+
+pub fn read_idx<R: Read>(reader: &mut R) -> Result<Idx, DatasetError> {
+    let header = read_bytes(reader, 4)?;
+    let magic = [header[0], header[1], header[2], header[3]];
+
+    if magic[0] != 0 || magic[1] != 0 {
+        return Err(DatasetError::BadMagic(magic))
+    }
+
+    let dims = (0..magic[3]).map(|_| read_be_u32(reader).map(|dim| dim as usize))
+        .collect::<Result<Vec<usize>, DatasetError>>()?;
+
+    let idx = match magic[2] {
+        0x08 => Idx::U8(read_array(reader, &dims, 1, |b| b[0])?),
+        0x09 => Idx::I8(read_array(reader, &dims, 1, |b| b[0] as i8)?),
+        0x0B => Idx::I16(read_array(reader, &dims, 2, |b| {
+            i16::from_be_bytes([b[0], b[1]])
+        })?),
+        0x0C => Idx::I32(read_array(reader, &dims, 4, |b| {
+            i32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        })?),
+        0x0D => Idx::F32(read_array(reader, &dims, 4, |b| {
+            f32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        })?),
+        0x0E => Idx::F64(read_array(reader, &dims, 8, |b| {
+            f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+        })?),
+        _ => return Err(DatasetError::BadMagic(magic)),
+    };
+    Ok(idx)
+}
+
+fn read_array<R: Read, V>(reader: &mut R, dims: &[usize], size: usize, decode: fn(&[u8]) -> V) -> Result<ArrayD<V>, DatasetError> {
+    // product of dims values of size bytes each
+    let len = dims.iter().try_fold(1usize, |len, &dim| len.checked_mul(dim));
+    let bytes = len.and_then(|len| len.checked_mul(size)).ok_or_else(|| DatasetError::ShapeMismatch {
+        expected: "a shape that fits in memory".to_string(),
+        found: dims.to_vec()
+    })?;
+
+    let values = read_bytes(reader, bytes)?.chunks_exact(size).map(decode).collect::<Vec<V>>();
+    Ok(ArrayD::from_shape_vec(IxDyn(dims), values).unwrap())
+}
+
+fn read_be_u32<R: Read>(reader: &mut R) -> Result<u32, DatasetError> {
+    let buf = read_bytes(reader, 4)?;
+    Ok(u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, DatasetError> {
+    // read_exact would lose how much was there, the buffer grows with the data instead of trusting the header
+    // a gzip stream cut short fails with UnexpectedEof instead of ending early (see dataset::open),
+    // the bytes decoded before are kept in buf
+    let mut buf = Vec::new();
+    match reader.take(len as u64).read_to_end(&mut buf) {
+        Err(error) if error.kind() != io::ErrorKind::UnexpectedEof => {
+            return Err(error.into())
+        },
+        _ => (),
+    }
+    if buf.len() < len {
+        return Err(DatasetError::Truncated { expected: len, found: buf.len() })
+    }
+    Ok(buf)
+}
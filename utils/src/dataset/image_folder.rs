@@ -0,0 +1,128 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+use ndarray::{Array1, Array2};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dataset::{Dataset, DatasetError, Sample, Split};
+use crate::utils::one_hot;
+
+// images sorted into one directory per class:
+// root/cat/001.png, root/cat/002.jpg, root/dog/001.png, ...
+// the classes are the sorted directory names, their index is the label
+const EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+// how an image is brought to the target size
+// Stretch: resize to exactly width x height, the aspect ratio is not kept
+// Crop: resize keeping the aspect ratio until the image covers width x height, then crop the center
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resize {
+    Stretch,
+    Crop
+}
+
+pub struct ImageFolder {
+    pub root: PathBuf,
+    pub classes: Vec<String>,
+    pub samples: Vec<(PathBuf, usize)>,
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub resize: Resize
+}
+
+impl ImageFolder {
+    pub fn new<P: AsRef<Path>>(root: P, width: usize, height: usize, channels: usize, resize: Resize) -> Result<ImageFolder, DatasetError> {
+        // channels: 1 (grayscale) or 3 (RGB), the in_channel of the first Conv3D
+        if channels != 1 && channels != 3 {
+            return Err(DatasetError::ShapeMismatch {
+                expected: "1 (grayscale) or 3 (RGB) channels".to_string(),
+                found: vec![channels]
+            })
+        }
+        let root = root.as_ref().to_path_buf();
+
+        let classes = sorted_entries(&root, |path| path.is_dir())?.into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<String>>();
+
+        let mut samples = vec![];
+        for (label, class) in classes.iter().enumerate() {
+            for path in sorted_entries(&root.join(class), is_image)? {
+                samples.push((path, label));
+            }
+        }
+
+        Ok(ImageFolder { root, classes, samples, width, height, channels, resize })
+    }
+
+    pub fn class_to_idx(&self) -> HashMap<String, usize> {
+        self.classes.iter().enumerate().map(|(index, class)| (class.clone(), index)).collect()
+    }
+
+    pub fn image(&self, index: usize) -> Result<Vec<Array2<f32>>, DatasetError> {
+        // [channel, height, width] in [0, 1]
+        let path = &self.samples[index].0;
+        let image = image::open(path).map_err(|error| DatasetError::Image(path.clone(), error))?;
+        Ok(self.to_channels(image))
+    }
+
+    pub fn load(&self) -> Result<Split, DatasetError> {
+        // every image [sample, channel, height, width] and one-hot labels [sample, classes]
+        let images = (0..self.len()).map(|index| self.image(index))
+            .collect::<Result<Vec<Vec<Array2<f32>>>, DatasetError>>()?;
+        let labels = Array2::from_shape_fn((self.len(), 1), |(s, _)| self.samples[s].1 as f32);
+        Ok((images, one_hot(labels, self.classes.len())))
+    }
+
+    fn to_channels(&self, image: DynamicImage) -> Vec<Array2<f32>> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        let image = match self.resize {
+            Resize::Stretch => image.resize_exact(width, height, FilterType::Triangle),
+            Resize::Crop => image.resize_to_fill(width, height, FilterType::Triangle),
+        };
+
+        // interleaved pixels -> one matrix per channel
+        let pixels = if self.channels == 1 { image.to_luma8().into_raw() } else { image.to_rgb8().into_raw() };
+        (0..self.channels).map(|c| {
+            Array2::from_shape_fn((self.height, self.width), |(i, j)| {
+                pixels[(i * self.width + j) * self.channels + c] as f32 / 255.
+            })
+        }).collect::<Vec<Array2<f32>>>()
+    }
+}
+
+impl Dataset for ImageFolder {
+    // the images are decoded when they are asked for, the directory can be larger than memory
+    fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn get(&self, index: usize) -> Result<Sample, DatasetError> {
+        let mut label = Array1::zeros(self.classes.len());
+        label[self.samples[index].1] = 1.;
+        Ok((self.image(index)?, label))
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| {
+        EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+    })
+}
+
+fn sorted_entries(dir: &Path, keep: fn(&Path) -> bool) -> Result<Vec<PathBuf>, DatasetError> {
+    // the entries kept by keep, hidden ones skipped, sorted so the labels do not depend on the file system
+    let with_path = |error: std::io::Error| std::io::Error::new(error.kind(), format!("{}: {}", dir.display(), error));
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir).map_err(with_path)? {
+        let path = entry.map_err(with_path)?.path();
+        let hidden = path.file_name().is_none_or(|name| name.to_string_lossy().starts_with('.'));
+        if !hidden && keep(&path) {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
@@ -0,0 +1,263 @@
+use ndarray::{stack, Array1, Array2, ArrayView2, Axis};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::dataset::{DatasetError, Split, Transform};
+use crate::random;
+
+// one sample: the channels [channel, height, width] and the label row (one-hot, or the values of a regression)
+pub type Sample = (Vec<Array2<f32>>, Array1<f32>);
+
+// anything indexable that can load its samples one by one, so it does not have to fit in memory
+pub trait Dataset: Send + Sync {
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> Result<Sample, DatasetError>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn collate(&self, samples: Vec<Sample>) -> Split {
+        // [sample, channel, height, width] and the labels [sample, label]
+        let labels = stack_rows(samples.iter().map(|(_, label)| label.view().insert_axis(Axis(0))).collect());
+        let inputs = samples.into_iter().map(|(input, _)| input).collect::<Vec<Vec<Array2<f32>>>>();
+        (inputs, labels)
+    }
+}
+
+// samples in memory, as returned by load_mnist, load_cifar10, ImageFolder::load
+pub struct TensorDataset {
+    pub inputs: Vec<Vec<Array2<f32>>>,
+    pub labels: Array2<f32>
+}
+
+impl TensorDataset {
+    pub fn new(inputs: Vec<Vec<Array2<f32>>>, labels: Array2<f32>) -> TensorDataset {
+        assert!(inputs.len() == labels.nrows(), "{} samples but {} labels", inputs.len(), labels.nrows());
+        TensorDataset { inputs, labels }
+    }
+}
+
+impl Dataset for TensorDataset {
+    fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    fn get(&self, index: usize) -> Result<Sample, DatasetError> {
+        Ok((self.inputs[index].clone(), self.labels.row(index).to_owned()))
+    }
+}
+
+// rows of features in memory, as returned by load_csv ([1, 1, sample, features])
+// a sample is one channel [1, features], the batches keep the layout of FullLayer with boundary 0
+pub struct TableDataset {
+    pub features: Array2<f32>,
+    pub labels: Array2<f32>
+}
+
+impl TableDataset {
+    pub fn new(features: Array2<f32>, labels: Array2<f32>) -> TableDataset {
+        assert!(features.nrows() == labels.nrows(), "{} samples but {} labels", features.nrows(), labels.nrows());
+        TableDataset { features, labels }
+    }
+}
+
+impl Dataset for TableDataset {
+    fn len(&self) -> usize {
+        self.features.nrows()
+    }
+
+    fn get(&self, index: usize) -> Result<Sample, DatasetError> {
+        let features = self.features.row(index).to_owned().insert_axis(Axis(0));
+        Ok((vec![features], self.labels.row(index).to_owned()))
+    }
+
+    fn collate(&self, samples: Vec<Sample>) -> Split {
+        // [1, 1, sample, features] and the labels [sample, label]
+        let features = stack_rows(samples.iter().map(|(input, _)| input[0].view()).collect());
+        let labels = stack_rows(samples.iter().map(|(_, label)| label.view().insert_axis(Axis(0))).collect());
+        (vec![vec![features]], labels)
+    }
+}
+
+// the order the samples of an epoch are visited in
+// Sequential: 0, 1, 2, ...
+// Random: a new permutation every epoch, drawn from the generator of random (reproducible with set_seed)
+// Weighted: `samples` indices drawn with replacement, index i with probability weights[i] / sum(weights),
+// to oversample the rare classes of an unbalanced dataset
+#[derive(Clone, Debug)]
+pub enum Sampler {
+    Sequential,
+    Random,
+    Weighted { weights: Vec<f64>, samples: usize }
+}
+
+impl Sampler {
+    pub fn indices(&self, len: usize) -> Result<Vec<usize>, DatasetError> {
+        // the weights are checked before anything is drawn from random
+        match self {
+            Sampler::Sequential => Ok((0..len).collect()),
+            Sampler::Random => {
+                let mut indices = (0..len).collect::<Vec<usize>>();
+                random::shuffle(&mut indices);
+                Ok(indices)
+            },
+            Sampler::Weighted { weights, samples } => {
+                if weights.len() != len {
+                    return Err(DatasetError::WeightCountMismatch { samples: len, weights: weights.len() })
+                }
+                let distribution = WeightedIndex::new(weights)?;
+                Ok(random::with_rng(|rng| (0..*samples).map(|_| distribution.sample(rng)).collect()))
+            },
+        }
+    }
+}
+
+// the batches of a dataset, an epoch per call of iter
+// workers == 0 loads the batches in the calling thread, when the training asks for them
+// workers > 0 loads them on background threads, each keeping up to `prefetch` batches ready,
+// the batches still come out in the order of the sampler
+// transform augments every sample as it is loaded, with a generator per batch drawn from random
+pub struct DataLoader<D> {
+    pub dataset: Arc<D>,
+    pub batch_size: usize,
+    pub sampler: Sampler,
+    pub drop_last: bool,
+    pub workers: usize,
+    pub prefetch: usize,
+    pub transform: Option<Arc<dyn Transform>>
+}
+
+impl<D: Dataset + 'static> DataLoader<D> {
+    pub fn new(dataset: D, batch_size: usize, sampler: Sampler) -> DataLoader<D> {
+        assert!(batch_size > 0, "batch_size must be at least 1");
+        DataLoader {
+            dataset: Arc::new(dataset),
+            batch_size,
+            sampler,
+            drop_last: false,
+            workers: 0,
+            prefetch: 2,
+            transform: None
+        }
+    }
+
+    pub fn samples(&self) -> usize {
+        // samples per epoch, without the ones dropped by drop_last
+        let samples = match &self.sampler {
+            Sampler::Weighted { samples, .. } => *samples,
+            _ => self.dataset.len(),
+        };
+        if self.drop_last { samples - samples % self.batch_size } else { samples }
+    }
+
+    pub fn len(&self) -> usize {
+        // batches per epoch
+        self.samples().div_ceil(self.batch_size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Result<Batches<D>, DatasetError> {
+        // fails when the sampler does not fit the dataset
+        let mut indices = self.sampler.indices(self.dataset.len())?;
+        indices.truncate(self.samples());
+        // the seeds are drawn here, in the calling thread, so they do not depend on the workers
+        let batches = indices.chunks(self.batch_size).map(|batch| {
+            let seed = self.transform.as_ref().map(|_| random::with_rng(|rng| rng.gen::<u64>()));
+            (batch.to_vec(), seed)
+        }).collect::<Vec<(Vec<usize>, Option<u64>)>>();
+        let transform = self.transform.clone();
+
+        if self.workers == 0 {
+            return Ok(Batches {
+                dataset: Arc::clone(&self.dataset),
+                transform,
+                batches,
+                next: 0,
+                receivers: vec![],
+                handles: vec![]
+            })
+        }
+
+        // worker w loads the batches w, w + workers, w + 2 * workers, ...
+        let (receivers, handles) = (0..self.workers).map(|w| {
+            let (sender, receiver) = sync_channel(self.prefetch);
+            let dataset = Arc::clone(&self.dataset);
+            let transform = self.transform.clone();
+            let batches = batches.iter().skip(w).step_by(self.workers).cloned().collect::<Vec<(Vec<usize>, Option<u64>)>>();
+
+            let handle = thread::spawn(move || {
+                for (batch, seed) in batches {
+                    // the receiver is gone when the iteration stopped early
+                    if sender.send(load(&*dataset, &batch, transform.as_deref(), seed)).is_err() {
+                        break;
+                    }
+                }
+            });
+            (receiver, handle)
+        }).unzip();
+
+        Ok(Batches { dataset: Arc::clone(&self.dataset), transform, batches, next: 0, receivers, handles })
+    }
+}
+
+pub struct Batches<D> {
+    dataset: Arc<D>,
+    transform: Option<Arc<dyn Transform>>,
+    batches: Vec<(Vec<usize>, Option<u64>)>,
+    next: usize,
+    receivers: Vec<Receiver<Result<Split, DatasetError>>>,
+    handles: Vec<JoinHandle<()>>
+}
+
+impl<D: Dataset> Iterator for Batches<D> {
+    type Item = Result<Split, DatasetError>;
+
+    fn next(&mut self) -> Option<Result<Split, DatasetError>> {
+        if self.next == self.batches.len() {
+            return None
+        }
+        let batch = if self.receivers.is_empty() {
+            let (batch, seed) = &self.batches[self.next];
+            load(&*self.dataset, batch, self.transform.as_deref(), *seed)
+        } else {
+            self.receivers[self.next % self.receivers.len()].recv().expect("a data loader worker panicked")
+        };
+        self.next += 1;
+        Some(batch)
+    }
+}
+
+impl<D> Drop for Batches<D> {
+    fn drop(&mut self) {
+        // unblock the workers waiting to send, then wait for them to finish their current batch
+        self.receivers.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn load<D: Dataset>(dataset: &D, indices: &[usize], transform: Option<&dyn Transform>, seed: Option<u64>) -> Result<Split, DatasetError> {
+    let mut samples = indices.iter().map(|&index| dataset.get(index)).collect::<Result<Vec<Sample>, DatasetError>>()?;
+    if let (Some(transform), Some(seed)) = (transform, seed) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        samples = samples.into_iter().map(|(input, label)| (transform.apply(input, &mut rng), label)).collect();
+    }
+    Ok(dataset.collate(samples))
+}
+
+fn stack_rows(rows: Vec<ArrayView2<f32>>) -> Array2<f32> {
+    if rows.is_empty() {
+        return Array2::zeros((0, 0))
+    }
+    stack(Axis(0), &rows).expect("every sample of a batch must have the same shape")
+}
@@ -1,70 +1,122 @@
-use ndarray::Array2;
+use ndarray::{Array2, Axis, Ix3};
 
-use crate::utils::{one_hot, matrix_to_tensor};
-use std::path::Path;
+use flate2::bufread::GzDecoder;
+
+use crate::utils::one_hot;
 use std::fs::File;
-use std::io::{self, Read};
-
-pub fn load_mnist<P: AsRef<Path>>(
-    path: Vec<P>,
-) -> ((Vec<Vec<Array2<f32>>>, Array2<f32>), (Vec<Vec<Array2<f32>>>, Array2<f32>)) {
-    let mut path_iter = path.into_iter();
-    let (train_x, num_image_train): (Vec<f32>, usize) = load_images(path_iter.next().unwrap());
-    let (train_y, num_label_train): (Vec<f32>, usize) = load_labels(path_iter.next().unwrap());
-
-    let (test_x, num_image_test): (Vec<f32>, usize) = load_images(path_iter.next().unwrap());
-
-    let (test_y, num_label_test): (Vec<f32>, usize) = load_labels(path_iter.next().unwrap());
-
-    let x_train = Array2::from_shape_vec((num_image_train, 28 * 28), train_x).unwrap();
-    let y_train = Array2::from_shape_vec((num_label_train, 1), train_y).unwrap();
-    let x_test = Array2::from_shape_vec((num_image_test, 28 * 28), test_x).unwrap();
-    let y_test = Array2::from_shape_vec((num_label_test, 1), test_y).unwrap();
-    (
-        (matrix_to_tensor(x_train), one_hot(y_train, 10)),
-        (matrix_to_tensor(x_test), one_hot(y_test, 10)),
-    )
-}
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
 
-fn load_images<P: AsRef<Path>>(path: P) -> (Vec<f32>, usize) {
-    let file = File::open(path).expect("please sure the data file exists");
-    let ref mut buf_reader = io::BufReader::new(file);
-    let magic = read_be_u32(buf_reader);
+pub mod cifar;
+pub mod error;
+pub mod idx;
+pub mod image_folder;
+pub mod loader;
+pub mod normalize;
+pub mod split;
+pub mod tabular;
+pub mod transform;
+pub use cifar::{load_cifar10, load_cifar10_batches, load_cifar100, load_cifar100_batches, Split100};
+pub use error::DatasetError;
+pub use idx::{Idx, load_idx, read_idx};
+pub use image_folder::{ImageFolder, Resize};
+pub use loader::{Batches, DataLoader, Dataset, Sample, Sampler, TableDataset, TensorDataset};
+pub use normalize::Normalize;
+pub use split::{select, stratified_kfold, stratified_split, Subset};
+pub use tabular::{load_csv, Column, CsvOptions, CsvSchema, Task};
+pub use transform::{
+    ColorJitter, Compose, ElasticDistortion, GaussianNoise, HorizontalFlip, RandomAffine, RandomCrop,
+    RandomErasing, RandomRotation, Transform, VerticalFlip
+};
 
-    if magic != 2051 {
-        panic!("Invalid magic number, expect 2051, got {}", magic)
+// gzip streams start with 1f 8b
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>, DatasetError> {
+    // buffered reader over the file, decompressed on the fly when it is gzip
+    // the errors carry the path, io::Error does not
+    let path = path.as_ref();
+    let with_path = |error: io::Error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error));
+
+    let mut reader = BufReader::new(File::open(path).map_err(with_path)?);
+    let gzip = reader.fill_buf().map_err(with_path)?.starts_with(&GZIP_MAGIC);
+    if gzip {
+        Ok(Box::new(Gzip(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
     }
+}
+
+// flate2 1.0 reports a gzip stream cut short as a corrupt stream (InvalidInput), like a stream corrupted in the middle,
+// the error becomes UnexpectedEof only when the whole file was read, so that it is reported as a truncated file
+struct Gzip(GzDecoder<BufReader<File>>);
 
-    let num_image = read_be_u32(buf_reader) as usize;
-    let rows = read_be_u32(buf_reader) as usize;
-    let cols = read_be_u32(buf_reader) as usize;
+impl Read for Gzip {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(error) if error.kind() == io::ErrorKind::InvalidInput => {
+                match self.0.get_mut().fill_buf() {
+                    Ok([]) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, error)),
+                    _ => Err(error),
+                }
+            },
+            result => result,
+        }
+    }
+}
 
-    assert!(rows == 28 && cols == 28);
+pub type Split = (Vec<Vec<Array2<f32>>>, Array2<f32>);
 
-    let mut buf: Vec<u8> = vec![0 as u8; num_image * rows * cols];
-    let _ = buf_reader.read_exact(buf.as_mut());
-    let ret: Vec<f32> = buf.into_iter().map(|x| (x as f32) / 255.).collect();
-    (ret, num_image)
+pub fn load_mnist<P: AsRef<Path>>(path: [P; 4]) -> Result<(Split, Split), DatasetError> {
+    // also loads Fashion-MNIST, KMNIST and the other 10-class datasets in the MNIST layout
+    // path: train images, train labels, test images, test labels
+    let [train_images, train_labels, test_images, test_labels] = path;
+    let train = load_split(train_images, train_labels)?;
+    let test = load_split(test_images, test_labels)?;
+    Ok((train, test))
 }
 
-fn load_labels<P: AsRef<Path>>(path: P) -> (Vec<f32>, usize) {
-    let ref mut buf_reader =
-        io::BufReader::new(File::open(path).expect("please sure label data exists"));
-    let magic = read_be_u32(buf_reader);
+fn load_split<P: AsRef<Path>>(images: P, labels: P) -> Result<Split, DatasetError> {
+    let images = load_images(images)?;
+    let labels = load_labels(labels)?;
 
-    if magic != 2049 {
-        panic!("invalid magic number, expect 2049, got {}", magic)
+    if images.len() != labels.nrows() {
+        return Err(DatasetError::LabelCountMismatch { samples: images.len(), labels: labels.nrows() })
     }
+    // one_hot would turn an unknown label into a row of zeros, as in the CIFAR loader
+    let classes = 10;
+    if let Some(&label) = labels.iter().find(|&&label| !(0..classes).any(|class| class as f32 == label)) {
+        return Err(DatasetError::LabelOutOfRange { label: label as usize, classes })
+    }
+    Ok((images, one_hot(labels, classes)))
+}
 
-    let num_label = read_be_u32(buf_reader) as usize;
-    let mut buf: Vec<u8> = vec![0 as u8; num_label];
-    let _ = buf_reader.read_exact(buf.as_mut());
-    let ret: Vec<f32> = buf.into_iter().map(|x| x as f32).collect();
-    (ret, num_label)
+pub fn load_images<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<Array2<f32>>>, DatasetError> {
+    // [sample, rows, cols] IDX file -> [sample, 1, rows, cols]
+    // u8 pixels are scaled to [0, 1], the other data types are kept as they are
+    let images = load_idx(path)?;
+    let scale = if images.dtype() == "u8" { 255. } else { 1. };
+    let images = images.to_f32();
+    let images = match images.view().into_dimensionality::<Ix3>() {
+        Ok(images) => images,
+        Err(_) => return Err(DatasetError::ShapeMismatch {
+            expected: "[sample, rows, cols]".to_string(),
+            found: images.shape().to_vec()
+        }),
+    };
+
+    Ok(images.axis_iter(Axis(0)).map(|image| {
+        vec![image.mapv(|x| x / scale)]
+    }).collect::<Vec<Vec<Array2<f32>>>>())
 }
 
-fn read_be_u32<T: Read>(reader: &mut T) -> u32 {
-    let mut buf = [0 as u8; 4];
-    let _ = reader.read_exact(&mut buf);
-    u32::from_be_bytes(buf)
-}
\ No newline at end of file
+pub fn load_labels<P: AsRef<Path>>(path: P) -> Result<Array2<f32>, DatasetError> {
+    // [sample] IDX file -> [sample, 1]
+    let labels = load_idx(path)?.to_f32();
+    if labels.ndim() != 1 {
+        return Err(DatasetError::ShapeMismatch { expected: "[sample]".to_string(), found: labels.shape().to_vec() })
+    }
+
+    let samples = labels.len();
+    Ok(labels.into_shape((samples, 1)).unwrap())
+}
@@ -0,0 +1,70 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{Dataset, DatasetError};
+use crate::float::Float;
+
+// (x - mean[c]) / std[c] for every pixel of channel c
+// fitted on the training set, then kept with the model (Graph::normalize, Checkpoint::normalize) so inference preprocesses the same way
+// not a Transform: the model applies it, a DataLoader would apply it a second time
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Normalize {
+    pub mean: Vec<f32>,
+    pub std: Vec<f32>
+}
+
+impl Normalize {
+    pub fn new(mean: Vec<f32>, std: Vec<f32>) -> Normalize {
+        assert!(mean.len() == std.len(), "{} means for {} standard deviations", mean.len(), std.len());
+        Normalize { mean, std }
+    }
+
+    pub fn fit<D: Dataset>(dataset: &D) -> Result<Normalize, DatasetError> {
+        // one pass over the samples, summed in f64 so large datasets do not lose precision
+        // a constant channel keeps std 1 instead of dividing by 0
+        let mut sum: Vec<f64> = vec![];
+        let mut squares: Vec<f64> = vec![];
+        let mut count: Vec<f64> = vec![];
+
+        for index in 0..dataset.len() {
+            let (input, _) = dataset.get(index)?;
+            if sum.is_empty() {
+                sum = vec![0.; input.len()];
+                squares = vec![0.; input.len()];
+                count = vec![0.; input.len()];
+            }
+            if input.len() != sum.len() {
+                return Err(DatasetError::ShapeMismatch {
+                    expected: format!("{} channels", sum.len()),
+                    found: vec![input.len()]
+                })
+            }
+            for (c, channel) in input.iter().enumerate() {
+                sum[c] += channel.iter().map(|&x| x as f64).sum::<f64>();
+                squares[c] += channel.iter().map(|&x| x as f64 * x as f64).sum::<f64>();
+                count[c] += channel.len() as f64;
+            }
+        }
+
+        let mean = sum.iter().zip(count.iter()).map(|(s, n)| s / n).collect::<Vec<f64>>();
+        let std = squares.iter().zip(count.iter()).zip(mean.iter()).map(|((s, n), m)| {
+            let std = (s / n - m * m).max(0.).sqrt() as f32;
+            if std > 0. { std } else { 1. }
+        }).collect::<Vec<f32>>();
+        Ok(Normalize::new(mean.into_iter().map(|m| m as f32).collect(), std))
+    }
+
+    pub fn normalize<T: Float>(&self, inputs: &[Vec<Array2<T>>]) -> Vec<Vec<Array2<T>>> {
+        // inputs [sample, channel, height, width]
+        inputs.iter().map(|channels| self.channels(channels)).collect()
+    }
+
+    fn channels<T: Float>(&self, channels: &[Array2<T>]) -> Vec<Array2<T>> {
+        assert!(channels.len() == self.mean.len(), "normalize {} channels, got {}", self.mean.len(), channels.len());
+        channels.iter().zip(self.mean.iter().zip(self.std.iter())).map(|(channel, (&mean, &std))| {
+            let (mean, std) = (T::from_f32(mean), T::from_f32(std));
+            channel.mapv(|x| (x - mean) / std)
+        }).collect()
+    }
+}
+
@@ -0,0 +1,99 @@
+use ndarray::{Array2, Axis};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::sync::Arc;
+
+use crate::dataset::{Dataset, DatasetError, Sample, Split};
+use crate::float::Float;
+use crate::utils::kernels::argmax;
+
+// splits of a dataset by sample index, stratified: every class keeps its share in every part
+// the classes are the argmax of the one-hot labels [sample, classes], a single label column is one class
+// the shuffling uses its own generator seeded with `seed`, the same seed gives the same split
+
+pub fn stratified_split<T: Float>(labels: &Array2<T>, fraction: f32, seed: u64) -> (Vec<usize>, Vec<usize>) {
+    // (train, validation) indices in increasing order, the validation part has `fraction` of every class
+    assert!((0. ..=1.).contains(&fraction), "the validation fraction must be in [0, 1], got {}", fraction);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut train = vec![];
+    let mut validation = vec![];
+
+    for mut class in classes(labels) {
+        class.shuffle(&mut rng);
+        let count = (class.len() as f32 * fraction).round() as usize;
+        validation.extend_from_slice(&class[..count]);
+        train.extend_from_slice(&class[count..]);
+    }
+    train.sort_unstable();
+    validation.sort_unstable();
+    (train, validation)
+}
+
+pub fn stratified_kfold<T: Float>(labels: &Array2<T>, k: usize, seed: u64) -> Vec<(Vec<usize>, Vec<usize>)> {
+    // k (train, validation) pairs, every sample is in exactly one validation fold
+    // the samples of each class are dealt to the folds in turn, so the folds differ by at most one sample
+    assert!(k >= 2 && k <= labels.nrows(), "k must be in [2, {}], got {}", labels.nrows(), k);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut folds = vec![vec![]; k];
+    let mut next = 0;
+
+    for mut class in classes(labels) {
+        class.shuffle(&mut rng);
+        for index in class {
+            folds[next].push(index);
+            next = (next + 1) % k;
+        }
+    }
+
+    (0..k).map(|f| {
+        let mut validation = folds[f].clone();
+        let mut train = folds.iter().enumerate().filter(|&(other, _)| other != f)
+            .flat_map(|(_, fold)| fold.iter().cloned()).collect::<Vec<usize>>();
+        validation.sort_unstable();
+        train.sort_unstable();
+        (train, validation)
+    }).collect()
+}
+
+pub fn select<T: Float>(inputs: &[Vec<Array2<T>>], labels: &Array2<T>, indices: &[usize]) -> (Vec<Vec<Array2<T>>>, Array2<T>) {
+    // the samples of indices, in that order
+    let inputs = indices.iter().map(|&index| inputs[index].clone()).collect::<Vec<Vec<Array2<T>>>>();
+    (inputs, labels.select(Axis(0), indices))
+}
+
+// the samples of indices of a dataset, without loading or copying them
+pub struct Subset<D> {
+    pub dataset: Arc<D>,
+    pub indices: Vec<usize>
+}
+
+impl<D: Dataset> Subset<D> {
+    pub fn new(dataset: Arc<D>, indices: Vec<usize>) -> Subset<D> {
+        Subset { dataset, indices }
+    }
+}
+
+impl<D: Dataset> Dataset for Subset<D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> Result<Sample, DatasetError> {
+        self.dataset.get(self.indices[index])
+    }
+
+    fn collate(&self, samples: Vec<Sample>) -> Split {
+        self.dataset.collate(samples)
+    }
+}
+
+fn classes<T: Float>(labels: &Array2<T>) -> Vec<Vec<usize>> {
+    // the sample indices of every class
+    let labels = labels.as_standard_layout();
+    let mut classes = vec![vec![]; labels.ncols().max(1)];
+    for (index, row) in labels.outer_iter().enumerate() {
+        classes[argmax(row.as_slice().unwrap())].push(index);
+    }
+    classes
+}
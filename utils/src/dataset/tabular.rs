@@ -0,0 +1,216 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::dataset::{open, DatasetError, Split};
+
+// tables in CSV files with a header row, for networks of FullLayer (boundary 0)
+// the features come out as [1, 1, sample, features], the layout FullLayer::forward takes
+// numeric columns are standardized, the other columns are one-hot encoded
+// a column is categorical only when it is listed in CsvOptions::categorical,
+// a value of another column that is not a number is an InvalidValue (missing values included)
+// the statistics and categories are fitted on one file (the training set) and kept in a CsvSchema,
+// CsvSchema::load encodes the other files (validation, test) the same way
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Task {
+    // labels one-hot [sample, classes]
+    Classification,
+    // labels as they are [sample, 1]
+    Regression
+}
+
+pub struct CsvOptions {
+    pub label: String,
+    pub task: Task,
+    pub categorical: Vec<String>,
+    pub standardize: bool,
+    pub delimiter: u8
+}
+
+impl CsvOptions {
+    pub fn new(label: &str, task: Task) -> CsvOptions {
+        CsvOptions {
+            label: label.to_string(),
+            task,
+            categorical: vec![],
+            standardize: true,
+            delimiter: b','
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Column {
+    // (x - mean) / std, mean 0 and std 1 without standardization
+    Numeric { name: String, mean: f32, std: f32 },
+    // one feature per category
+    Categorical { name: String, categories: Vec<String> }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CsvSchema {
+    pub label: String,
+    pub task: Task,
+    pub classes: Vec<String>,
+    pub columns: Vec<Column>,
+    pub delimiter: u8
+}
+
+pub fn load_csv<P: AsRef<Path>>(path: P, options: &CsvOptions) -> Result<(Split, CsvSchema), DatasetError> {
+    // fits the schema on the file and encodes it
+    let (headers, rows) = read_table(path, options.delimiter)?;
+    let schema = CsvSchema::fit(&headers, &rows, options)?;
+    let split = schema.encode(&headers, &rows)?;
+    Ok((split, schema))
+}
+
+impl CsvSchema {
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<Split, DatasetError> {
+        // the columns are matched by name, their order in the file does not matter
+        let (headers, rows) = read_table(path, self.delimiter)?;
+        self.encode(&headers, &rows)
+    }
+
+    pub fn features(&self) -> usize {
+        // prev_neurons of the first FullLayer
+        self.columns.iter().map(|column| match column {
+            Column::Numeric { .. } => 1,
+            Column::Categorical { categories, .. } => categories.len(),
+        }).sum()
+    }
+
+    pub fn feature_names(&self) -> Vec<String> {
+        // name of a numeric column, name=category for a one-hot feature
+        self.columns.iter().flat_map(|column| match column {
+            Column::Numeric { name, .. } => vec![name.clone()],
+            Column::Categorical { name, categories } => {
+                categories.iter().map(|category| format!("{}={}", name, category)).collect()
+            },
+        }).collect()
+    }
+
+    fn fit(headers: &[String], rows: &[Vec<String>], options: &CsvOptions) -> Result<CsvSchema, DatasetError> {
+        let label = find(headers, &options.label)?;
+
+        let classes = match options.task {
+            Task::Classification => sorted(rows.iter().map(|row| row[label].clone()).collect()),
+            Task::Regression => vec![],
+        };
+
+        let columns = headers.iter().enumerate().filter(|&(c, _)| c != label).map(|(c, name)| {
+            if options.categorical.contains(name) {
+                return Ok(Column::Categorical {
+                    name: name.clone(),
+                    categories: sorted(rows.iter().map(|row| row[c].clone()).collect())
+                })
+            }
+            let numbers = rows.iter().map(|row| number(name, &row[c])).collect::<Result<Vec<f32>, DatasetError>>()?;
+            let (mean, std) = if options.standardize { mean_std(&numbers) } else { (0., 1.) };
+            Ok(Column::Numeric { name: name.clone(), mean, std })
+        }).collect::<Result<Vec<Column>, DatasetError>>()?;
+
+        Ok(CsvSchema {
+            label: options.label.clone(),
+            task: options.task,
+            classes,
+            columns,
+            delimiter: options.delimiter
+        })
+    }
+
+    fn encode(&self, headers: &[String], rows: &[Vec<String>]) -> Result<Split, DatasetError> {
+        let samples = rows.len();
+        let mut features = Array2::zeros((samples, self.features()));
+        let mut offset = 0;
+
+        for column in self.columns.iter() {
+            match column {
+                Column::Numeric { name, mean, std } => {
+                    let c = find(headers, name)?;
+                    for (s, row) in rows.iter().enumerate() {
+                        features[[s, offset]] = (number(name, &row[c])? - mean) / std;
+                    }
+                    offset += 1;
+                },
+                Column::Categorical { name, categories } => {
+                    let c = find(headers, name)?;
+                    for (s, row) in rows.iter().enumerate() {
+                        features[[s, offset + index(name, categories, &row[c])?]] = 1.;
+                    }
+                    offset += categories.len();
+                },
+            }
+        }
+
+        let c = find(headers, &self.label)?;
+        let labels = match self.task {
+            Task::Classification => {
+                let mut labels = Array2::zeros((samples, self.classes.len()));
+                for (s, row) in rows.iter().enumerate() {
+                    labels[[s, index(&self.label, &self.classes, &row[c])?]] = 1.;
+                }
+                labels
+            },
+            Task::Regression => {
+                let mut labels = Array2::zeros((samples, 1));
+                for (s, row) in rows.iter().enumerate() {
+                    labels[[s, 0]] = number(&self.label, &row[c])?;
+                }
+                labels
+            },
+        };
+
+        Ok((vec![vec![features]], labels))
+    }
+}
+
+fn read_table<P: AsRef<Path>>(path: P, delimiter: u8) -> Result<(Vec<String>, Vec<Vec<String>>), DatasetError> {
+    // raw or gzip-compressed, the fields trimmed, every row as long as the header
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .from_reader(open(path)?);
+
+    let headers = reader.headers()?.iter().map(String::from).collect::<Vec<String>>();
+    let rows = reader.records().map(|record| {
+        record.map(|record| record.iter().map(String::from).collect::<Vec<String>>())
+    }).collect::<Result<Vec<Vec<String>>, csv::Error>>()?;
+    Ok((headers, rows))
+}
+
+fn find(headers: &[String], name: &str) -> Result<usize, DatasetError> {
+    headers.iter().position(|header| header == name).ok_or_else(|| DatasetError::MissingColumn(name.to_string()))
+}
+
+fn number(column: &str, value: &str) -> Result<f32, DatasetError> {
+    // parse accepts NaN and inf, they would spread through the statistics and the training
+    match value.parse::<f32>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(DatasetError::InvalidValue { column: column.to_string(), value: value.to_string() }),
+    }
+}
+
+fn index(column: &str, values: &[String], value: &str) -> Result<usize, DatasetError> {
+    // a class or category the schema was not fitted on
+    values.iter().position(|v| v == value)
+        .ok_or_else(|| DatasetError::InvalidValue { column: column.to_string(), value: value.to_string() })
+}
+
+fn sorted(values: Vec<String>) -> Vec<String> {
+    // unique values, in numeric order when they are all numbers (2 before 10)
+    let mut values = values.into_iter().collect::<BTreeSet<String>>().into_iter().collect::<Vec<String>>();
+    if values.iter().all(|value| value.parse::<f64>().is_ok()) {
+        values.sort_by(|a, b| a.parse::<f64>().unwrap().total_cmp(&b.parse::<f64>().unwrap()));
+    }
+    values
+}
+
+fn mean_std(numbers: &[f32]) -> (f32, f32) {
+    // a constant column keeps std 1 instead of dividing by 0
+    let n = numbers.len().max(1) as f32;
+    let mean = numbers.iter().sum::<f32>() / n;
+    let std = (numbers.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / n).sqrt();
+    (mean, if std > 0. { std } else { 1. })
+}
@@ -0,0 +1,337 @@
+use ndarray::Array2;
+use ndarray_rand::rand_distr::StandardNormal;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+// data augmentation, applied to every sample [channel, height, width] when the DataLoader loads it
+// the geometric transforms move all the channels of a sample the same way
+// the DataLoader gives each batch its own generator seeded from random, so with set_seed
+// the augmented batches are the same whatever the number of workers
+pub trait Transform: Send + Sync {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>>;
+}
+
+// the transforms one after another
+pub struct Compose {
+    pub transforms: Vec<Box<dyn Transform>>
+}
+
+impl Compose {
+    pub fn new(transforms: Vec<Box<dyn Transform>>) -> Compose {
+        Compose { transforms }
+    }
+}
+
+impl Transform for Compose {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        self.transforms.iter().fold(image, |image, transform| transform.apply(image, rng))
+    }
+}
+
+// pad every side with `padding` zeros, then crop a random height x width window
+pub struct RandomCrop {
+    pub height: usize,
+    pub width: usize,
+    pub padding: usize
+}
+
+impl RandomCrop {
+    pub fn new(height: usize, width: usize, padding: usize) -> RandomCrop {
+        RandomCrop { height, width, padding }
+    }
+}
+
+impl Transform for RandomCrop {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        let (rows, cols) = image[0].dim();
+        let (padded_rows, padded_cols) = (rows + 2 * self.padding, cols + 2 * self.padding);
+        assert!(self.height <= padded_rows && self.width <= padded_cols,
+            "cannot crop {}x{} from a {}x{} image padded by {}", self.height, self.width, rows, cols, self.padding);
+
+        let top = rng.gen_range(0, padded_rows - self.height + 1);
+        let left = rng.gen_range(0, padded_cols - self.width + 1);
+        image.into_iter().map(|channel| {
+            Array2::from_shape_fn((self.height, self.width), |(i, j)| {
+                // position in the unpadded image, outside is padding
+                let (y, x) = ((top + i) as isize - self.padding as isize, (left + j) as isize - self.padding as isize);
+                if y < 0 || x < 0 || y >= rows as isize || x >= cols as isize { 0. } else { channel[[y as usize, x as usize]] }
+            })
+        }).collect()
+    }
+}
+
+// mirror left-right with probability p
+// not for digits and text, a flipped 7 is not a 7
+pub struct HorizontalFlip {
+    pub p: f32
+}
+
+impl HorizontalFlip {
+    pub fn new(p: f32) -> HorizontalFlip {
+        HorizontalFlip { p }
+    }
+}
+
+impl Transform for HorizontalFlip {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        if rng.gen::<f32>() >= self.p {
+            return image
+        }
+        image.into_iter().map(|channel| {
+            let cols = channel.ncols();
+            Array2::from_shape_fn(channel.dim(), |(i, j)| channel[[i, cols - 1 - j]])
+        }).collect()
+    }
+}
+
+// mirror upside down with probability p
+pub struct VerticalFlip {
+    pub p: f32
+}
+
+impl VerticalFlip {
+    pub fn new(p: f32) -> VerticalFlip {
+        VerticalFlip { p }
+    }
+}
+
+impl Transform for VerticalFlip {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        if rng.gen::<f32>() >= self.p {
+            return image
+        }
+        image.into_iter().map(|channel| {
+            let rows = channel.nrows();
+            Array2::from_shape_fn(channel.dim(), |(i, j)| channel[[rows - 1 - i, j]])
+        }).collect()
+    }
+}
+
+// rotate around the center by an angle in [-degrees, degrees], the corners are filled with 0
+pub struct RandomRotation {
+    pub degrees: f32
+}
+
+impl RandomRotation {
+    pub fn new(degrees: f32) -> RandomRotation {
+        RandomRotation { degrees }
+    }
+}
+
+impl Transform for RandomRotation {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        let angle = uniform(rng, -self.degrees, self.degrees).to_radians();
+        affine(image, [[angle.cos(), -angle.sin()], [angle.sin(), angle.cos()]], (0., 0.))
+    }
+}
+
+// rotation in [-degrees, degrees], shift in [-translate, translate] of the height/width,
+// zoom in [scale.0, scale.1] and horizontal shear in [-shear, shear] degrees, around the center
+pub struct RandomAffine {
+    pub degrees: f32,
+    pub translate: f32,
+    pub scale: (f32, f32),
+    pub shear: f32
+}
+
+impl RandomAffine {
+    pub fn new(degrees: f32, translate: f32, scale: (f32, f32), shear: f32) -> RandomAffine {
+        // a zoom of 0 collapses the image, affine could not invert it
+        assert!(scale.0 > 0. && scale.0 <= scale.1, "the scale must be a range of positive zooms, got {:?}", scale);
+        RandomAffine { degrees, translate, scale, shear }
+    }
+}
+
+impl Transform for RandomAffine {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        let (rows, cols) = image[0].dim();
+        let angle = uniform(rng, -self.degrees, self.degrees).to_radians();
+        let shift = (
+            uniform(rng, -self.translate, self.translate) * rows as f32,
+            uniform(rng, -self.translate, self.translate) * cols as f32
+        );
+        let scale = uniform(rng, self.scale.0, self.scale.1);
+        let shear = uniform(rng, -self.shear, self.shear).to_radians().tan();
+
+        // rotation * shear * scale, on (y, x)
+        let (sin, cos) = angle.sin_cos();
+        let matrix = [
+            [cos * scale, (cos * shear - sin) * scale],
+            [sin * scale, (sin * shear + cos) * scale]
+        ];
+        affine(image, matrix, shift)
+    }
+}
+
+// elastic distortion (Simard et al. 2003): every pixel moves by a random displacement field,
+// smoothed by a gaussian of std sigma and scaled by alpha (in pixels), for handwriting
+pub struct ElasticDistortion {
+    pub alpha: f32,
+    pub sigma: f32
+}
+
+impl ElasticDistortion {
+    pub fn new(alpha: f32, sigma: f32) -> ElasticDistortion {
+        ElasticDistortion { alpha, sigma }
+    }
+}
+
+impl Transform for ElasticDistortion {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        let shape = image[0].dim();
+        let mut field = || {
+            let noise = Array2::from_shape_fn(shape, |_| uniform(rng, -1., 1.));
+            gaussian_blur(&noise, self.sigma) * self.alpha
+        };
+        let (dy, dx) = (field(), field());
+
+        image.iter().map(|channel| {
+            Array2::from_shape_fn(shape, |(i, j)| {
+                bilinear(channel, i as f32 + dy[[i, j]], j as f32 + dx[[i, j]])
+            })
+        }).collect()
+    }
+}
+
+// add gaussian noise of standard deviation std to every pixel
+pub struct GaussianNoise {
+    pub std: f32
+}
+
+impl GaussianNoise {
+    pub fn new(std: f32) -> GaussianNoise {
+        GaussianNoise { std }
+    }
+}
+
+impl Transform for GaussianNoise {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        image.into_iter().map(|channel| {
+            channel.mapv(|x| x + rng.sample::<f32, _>(StandardNormal) * self.std)
+        }).collect()
+    }
+}
+
+// cutout: with probability p, set a random rectangle of every channel to value
+// the rectangle covers a fraction in [area.0, area.1] of the image, its height / width is in [ratio.0, ratio.1]
+pub struct RandomErasing {
+    pub p: f32,
+    pub area: (f32, f32),
+    pub ratio: (f32, f32),
+    pub value: f32
+}
+
+impl RandomErasing {
+    pub fn new(p: f32, area: (f32, f32), ratio: (f32, f32), value: f32) -> RandomErasing {
+        RandomErasing { p, area, ratio, value }
+    }
+}
+
+impl Transform for RandomErasing {
+    fn apply(&self, mut image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        if rng.gen::<f32>() >= self.p {
+            return image
+        }
+        let (rows, cols) = image[0].dim();
+        let area = uniform(rng, self.area.0, self.area.1) * (rows * cols) as f32;
+        // the ratio is drawn on a log scale, so 1/2 and 2 are as likely
+        let ratio = uniform(rng, self.ratio.0.ln(), self.ratio.1.ln()).exp();
+        let height = ((area * ratio).sqrt().round() as usize).clamp(1, rows);
+        let width = ((area / ratio).sqrt().round() as usize).clamp(1, cols);
+
+        let top = rng.gen_range(0, rows - height + 1);
+        let left = rng.gen_range(0, cols - width + 1);
+        for channel in image.iter_mut() {
+            channel.slice_mut(ndarray::s![top..top + height, left..left + width]).fill(self.value);
+        }
+        image
+    }
+}
+
+// brightness: multiply by a factor in [1 - brightness, 1 + brightness]
+// contrast: scale the distance to the mean of the sample by a factor in [1 - contrast, 1 + contrast]
+// the result is clipped to [0, 1], the range of the loaders
+pub struct ColorJitter {
+    pub brightness: f32,
+    pub contrast: f32
+}
+
+impl ColorJitter {
+    pub fn new(brightness: f32, contrast: f32) -> ColorJitter {
+        ColorJitter { brightness, contrast }
+    }
+}
+
+impl Transform for ColorJitter {
+    fn apply(&self, image: Vec<Array2<f32>>, rng: &mut StdRng) -> Vec<Array2<f32>> {
+        let brightness = uniform(rng, 1. - self.brightness, 1. + self.brightness).max(0.);
+        let contrast = uniform(rng, 1. - self.contrast, 1. + self.contrast).max(0.);
+
+        let pixels = image.iter().map(|channel| channel.len()).sum::<usize>() as f32;
+        let mean = image.iter().map(|channel| channel.sum()).sum::<f32>() * brightness / pixels;
+        image.into_iter().map(|channel| {
+            channel.mapv(|x| ((x * brightness - mean) * contrast + mean).clamp(0., 1.))
+        }).collect()
+    }
+}
+
+fn uniform(rng: &mut StdRng, low: f32, high: f32) -> f32 {
+    // gen_range panics on an empty range, a range of 0 width is the constant
+    if low < high { rng.gen_range(low, high) } else { low }
+}
+
+fn affine(image: Vec<Array2<f32>>, matrix: [[f32; 2]; 2], shift: (f32, f32)) -> Vec<Array2<f32>> {
+    // output (y, x) = matrix * (input - center) + center + shift, sampled backwards with the inverse
+    let (rows, cols) = image[0].dim();
+    let center = ((rows as f32 - 1.) / 2., (cols as f32 - 1.) / 2.);
+    let det = matrix[0][0] * matrix[1][1] - matrix[0][1] * matrix[1][0];
+    assert!(det.is_normal(), "the affine matrix {:?} is not invertible", matrix);
+    let inverse = [
+        [matrix[1][1] / det, -matrix[0][1] / det],
+        [-matrix[1][0] / det, matrix[0][0] / det]
+    ];
+
+    image.iter().map(|channel| {
+        Array2::from_shape_fn((rows, cols), |(i, j)| {
+            let (y, x) = (i as f32 - center.0 - shift.0, j as f32 - center.1 - shift.1);
+            bilinear(
+                channel,
+                inverse[0][0] * y + inverse[0][1] * x + center.0,
+                inverse[1][0] * y + inverse[1][1] * x + center.1
+            )
+        })
+    }).collect()
+}
+
+fn bilinear(channel: &Array2<f32>, y: f32, x: f32) -> f32 {
+    // the pixels outside of the image are 0
+    let (rows, cols) = channel.dim();
+    let pixel = |i: isize, j: isize| {
+        if i < 0 || j < 0 || i >= rows as isize || j >= cols as isize { 0. } else { channel[[i as usize, j as usize]] }
+    };
+    let (y0, x0) = (y.floor(), x.floor());
+    let (dy, dx) = (y - y0, x - x0);
+    let (i, j) = (y0 as isize, x0 as isize);
+
+    pixel(i, j) * (1. - dy) * (1. - dx) + pixel(i, j + 1) * (1. - dy) * dx
+        + pixel(i + 1, j) * dy * (1. - dx) + pixel(i + 1, j + 1) * dy * dx
+}
+
+fn gaussian_blur(input: &Array2<f32>, sigma: f32) -> Array2<f32> {
+    // separable, rows then columns, the borders are clamped
+    if sigma <= 0. {
+        return input.to_owned()
+    }
+    let radius = (3. * sigma).ceil() as isize;
+    let kernel = (-radius..=radius).map(|k| (-(k * k) as f32 / (2. * sigma * sigma)).exp()).collect::<Vec<f32>>();
+    let total = kernel.iter().sum::<f32>();
+
+    let (rows, cols) = input.dim();
+    let clamp = |v: isize, len: usize| v.clamp(0, len as isize - 1) as usize;
+    let horizontal = Array2::from_shape_fn((rows, cols), |(i, j)| {
+        kernel.iter().enumerate().map(|(k, w)| w * input[[i, clamp(j as isize + k as isize - radius, cols)]]).sum::<f32>() / total
+    });
+    Array2::from_shape_fn((rows, cols), |(i, j)| {
+        kernel.iter().enumerate().map(|(k, w)| w * horizontal[[clamp(i as isize + k as isize - radius, rows), j]]).sum::<f32>() / total
+    })
+}
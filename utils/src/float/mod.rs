@@ -0,0 +1,56 @@
+use ndarray::{ArrayView2, ArrayViewMut2, LinalgScalar, ScalarOperand};
+use serde::Serialize;
+use crate::linalg;
+use serde::de::DeserializeOwned;
+
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{AddAssign, SubAssign, MulAssign, DivAssign};
+
+// element type of the layers, f32 by default
+// f64 is useful for gradient checking and for users who need the precision
+// DTYPE is written into the saved layers, a model must be loaded with the type it was saved with
+pub trait Float: num_traits::Float + LinalgScalar + ScalarOperand
+    + AddAssign + SubAssign + MulAssign + DivAssign + Sum
+    + Default + Debug + Display + Send + Sync + Serialize + DeserializeOwned {
+    const DTYPE: &'static str;
+
+    fn from_f32(x: f32) -> Self;
+
+    fn from_usize(x: usize) -> Self;
+
+    // c = alpha * a * b + beta * c, use linalg::gemm instead of calling it directly
+    fn gemm(alpha: Self, a: &ArrayView2<Self>, b: &ArrayView2<Self>, beta: Self, c: &mut ArrayViewMut2<Self>);
+}
+
+impl Float for f32 {
+    const DTYPE: &'static str = "f32";
+
+    fn from_f32(x: f32) -> f32 {
+        x
+    }
+
+    fn from_usize(x: usize) -> f32 {
+        x as f32
+    }
+
+    fn gemm(alpha: f32, a: &ArrayView2<f32>, b: &ArrayView2<f32>, beta: f32, c: &mut ArrayViewMut2<f32>) {
+        linalg::sgemm(alpha, a, b, beta, c)
+    }
+}
+
+impl Float for f64 {
+    const DTYPE: &'static str = "f64";
+
+    fn from_f32(x: f32) -> f64 {
+        x as f64
+    }
+
+    fn from_usize(x: usize) -> f64 {
+        x as f64
+    }
+
+    fn gemm(alpha: f64, a: &ArrayView2<f64>, b: &ArrayView2<f64>, beta: f64, c: &mut ArrayViewMut2<f64>) {
+        linalg::dgemm(alpha, a, b, beta, c)
+    }
+}
@@ -1,25 +1,27 @@
-use crate::propagation::Propagation;
+use crate::propagation::{Propagation, Gradients};
 use crate::utils;
-use utils::_flatten_withno_channel;
+use utils::{_flatten_withno_channel, _flatten_into};
 
-use ndarray::{Array, Array2, Axis};
-use ndarray_rand::rand_distr::StandardNormal;
-use ndarray_rand::RandomExt;
-use std::cell::{RefCell};
+use ndarray::{Array2, Axis};
+use crate::float::Float;
+use crate::linalg::{dot, gemm};
+use crate::workspace::{Scratch, resize, resize_matrix};
+use crate::random;
+use std::sync::RwLock;
 
 // boundary == out_channel
 // boundary == 0 means this FC layer is not connected with convolution layer or pooling layer
-pub struct FullLayer {
+pub struct FullLayer<T = f32> {
     pub neurons: usize,
     pub prev_neurons: usize,
-    pub alpha: f32,
+    pub alpha: T,
     pub boundary: usize,
-    pub weights: RefCell<Array2<f32>>,
-    pub bias: RefCell<Array2<f32>>,
+    pub weights: RwLock<Array2<T>>,
+    pub bias: RwLock<Array2<T>>,
 }
 
-impl Propagation for FullLayer {
-    fn forward(&self, inputs: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
+impl<T: Float> Propagation<T> for FullLayer<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
         // inputs [sample, channel, input_width, input_width]
         // output [1, 1, sample, neurons]
 
@@ -29,34 +31,67 @@ impl Propagation for FullLayer {
             inputs[0].to_owned()
         }; // [1, sample, channel * input_width * input_width]
         
-        let z = self.weights.borrow().dot(&flattened_input[0].t()) + &*self.bias.borrow(); // [neurons, sample]
+        let z = dot(&*self.weights.read().unwrap(), &flattened_input[0].t()) + &*self.bias.read().unwrap(); // [neurons, sample]
         vec![vec![z.reversed_axes()]]
     }
 
-    fn backward(&self, inputs: Vec<Vec<Array2<f32>>>, next_deltas: Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
+    fn forward_into(&self, inputs: &Vec<Vec<Array2<T>>>, output: &mut Vec<Vec<Array2<T>>>, scratch: &mut Scratch<T>) {
+        // same as forward, scratch.cols holds the flattened input and scratch.product the GEMM output
+        let Scratch { cols, product } = scratch;
+        let flattened_input = if self.boundary != 0 {
+            resize_matrix(cols, (inputs.len(), self.prev_neurons));
+            _flatten_into(inputs, cols.view_mut());
+            cols.view()
+        } else {
+            inputs[0][0].view()
+        }; // [sample, channel * input_width * input_width]
+        let sample = flattened_input.shape()[0];
+
+        resize_matrix(product, (self.neurons, sample));
+        gemm(T::one(), &*self.weights.read().unwrap(), &flattened_input.t(), T::zero(), product);
+        *product += &*self.bias.read().unwrap(); // [neurons, sample]
+
+        resize(output, 1, 1, (sample, self.neurons));
+        output[0][0].assign(&product.t());
+    }
+
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
         // input [1, 1, sample, prev_neurons] or [sample, channel, input_width, input_width]
         // next_deltas [1, 1, sample, neurons]
 
         let sample = next_deltas[0][0].shape()[0];
 
-        let flattened_input = if self.boundary != 1 {
-            _flatten_withno_channel(&inputs, self.prev_neurons)
+        let flattened_input = if self.boundary != 0 {
+            _flatten_withno_channel(inputs, self.prev_neurons)
         } else {
             inputs[0].to_owned()
         }; // [1, sample, channel * input_width * input_width]
 
-        let derivate_weight = next_deltas[0][0].t().dot(&flattened_input[0]);
+        let derivate_weight = dot(&next_deltas[0][0].t(), &flattened_input[0]); // [neurons, prev_neurons]
+        let derivate_bias = next_deltas[0][0].sum_axis(Axis(0)).insert_axis(Axis(1)); // [neurons, 1]
         let deltas = self.cal_delta(&next_deltas, sample);
-        
-        self.update(derivate_weight, &next_deltas[0][0], sample);
-        
-        deltas
+
+        (deltas, Gradients::new(vec![derivate_weight, derivate_bias], sample))
+    }
+
+    fn apply(&self, gradients: &Gradients<T>) {
+        self.update(&gradients.derivates[0], &gradients.derivates[1], gradients.samples);
+    }
+
+    fn parameters(&self) -> Vec<Array2<T>> {
+        vec![self.weights.read().unwrap().clone(), self.bias.read().unwrap().clone()]
+    }
+
+    fn set_parameters(&self, parameters: &[Array2<T>]) {
+        *self.weights.write().unwrap() = parameters[0].clone();
+        *self.bias.write().unwrap() = parameters[1].clone();
     }
 }
 
-impl FullLayer {
+impl<T: Float> FullLayer<T> {
 
-    pub fn new(neurons: usize, prev_neurons: usize, alpha: f32, boundary: usize) -> FullLayer {
+    pub fn new(neurons: usize, prev_neurons: usize, alpha: T, boundary: usize) -> FullLayer<T> {
         let (weights, bias) = FullLayer::initialization(neurons, prev_neurons);
 
         FullLayer {
@@ -64,27 +99,27 @@ impl FullLayer {
             prev_neurons,
             alpha,
             boundary,
-            weights: RefCell::new(weights),
-            bias: RefCell::new(bias)
+            weights: RwLock::new(weights),
+            bias: RwLock::new(bias)
         }
     }
 
 }
 
-impl FullLayer {
+impl<T: Float> FullLayer<T> {
 
-    fn initialization(neurons: usize, prev_neurons: usize) -> (Array2<f32>, Array2<f32>) {
+    fn initialization(neurons: usize, prev_neurons: usize) -> (Array2<T>, Array2<T>) {
         (
-            Array::random((neurons, prev_neurons), StandardNormal) * 0.05,
+            random::normal((neurons, prev_neurons)) * T::from_f32(0.05),
             Array2::zeros((neurons, 1))
         )
     }
 
-    fn cal_delta(&self, next_delta: &Vec<Vec<Array2<f32>>>, sample: usize) -> Vec<Vec<Array2<f32>>> {
+    fn cal_delta(&self, next_delta: &Vec<Vec<Array2<T>>>, sample: usize) -> Vec<Vec<Array2<T>>> {
         // next delta [1, 1, sample, neurons]
         // delta [sample, prev_neurons]
         // output [sample, out_channel, input_width, input_width]
-        let delta = next_delta[0][0].dot(&*self.weights.borrow());
+        let delta = dot(&next_delta[0][0], &*self.weights.read().unwrap());
 
         if self.boundary == 0 {
             vec![vec![delta]]
@@ -94,19 +129,19 @@ impl FullLayer {
 
             (0..sample).map(|_| {
                 (0..self.boundary).map(|_| {
-                    let v =  (0..width * width).map(|_| *data_iter.next().unwrap()).collect::<Vec<f32>>();
+                    let v =  (0..width * width).map(|_| *data_iter.next().unwrap()).collect::<Vec<T>>();
                     Array2::from_shape_vec((width, width), v).unwrap()
-                }).collect::<Vec<Array2<f32>>>()
-            }).collect::<Vec<Vec<Array2<f32>>>>()
+                }).collect::<Vec<Array2<T>>>()
+            }).collect::<Vec<Vec<Array2<T>>>>()
         }
 
     }
 
-    fn update(&self, derivate_weight: Array2<f32>, derivate_bias: &Array2<f32>, sample: usize) {
-        let cloned_weights = self.weights.borrow().clone();
-        let cloned_bias = self.bias.borrow().clone();
+    fn update(&self, derivate_weight: &Array2<T>, derivate_bias: &Array2<T>, sample: usize) {
+        let cloned_weights = self.weights.read().unwrap().clone();
+        let cloned_bias = self.bias.read().unwrap().clone();
 
-        *self.weights.borrow_mut() = cloned_weights - self.alpha * derivate_weight /  self.neurons as f32;
-        *self.bias.borrow_mut() = cloned_bias - self.alpha * derivate_bias.t().sum_axis(Axis(0)) / sample as f32;
+        *self.weights.write().unwrap() = cloned_weights - derivate_weight * self.alpha / T::from_usize(sample);
+        *self.bias.write().unwrap() = cloned_bias - derivate_bias * self.alpha / T::from_usize(sample);
     }
 }
\ No newline at end of file
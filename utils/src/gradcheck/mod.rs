@@ -0,0 +1,128 @@
+use ndarray::{indices, Array2};
+use crate::float::Float;
+use crate::network::{nn, forward};
+use std::fmt::{self, Display, Formatter};
+
+// gradient checking: the analytic gradients of Propagation::gradients are compared with central differences
+// (loss(x + h) - loss(x - h)) / 2h of every input element and every parameter element
+// a single layer is checked as a network of one layer
+// error = |analytic - numeric| / max(|analytic|, |numeric|, 1), relative for large gradients and absolute for small ones
+// max pooling and relu are not differentiable everywhere, their inputs must stay away from ties and zeros
+
+// max error of parameters()[tensor] of network[layer]
+pub struct GradError<T = f32> {
+    pub layer: usize,
+    pub tensor: usize,
+    pub error: T
+}
+
+pub struct GradCheck<T = f32> {
+    // max error of the deltas of the network input
+    pub input: T,
+    pub parameters: Vec<GradError<T>>
+}
+
+impl<T: Float> GradCheck<T> {
+    pub fn max_error(&self) -> T {
+        self.parameters.iter().fold(self.input, |acc, p| acc.max(p.error))
+    }
+}
+
+impl<T: Float> Display for GradCheck<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "input: {:?}", self.input)?;
+        for p in self.parameters.iter() {
+            writeln!(f, "layer {} tensor {}: {:?}", p.layer, p.tensor, p.error)?;
+        }
+        Ok(())
+    }
+}
+
+// loss returns (loss of the network output, derivative of the loss with respect to that output)
+pub fn gradcheck<T: Float, L>(network: &Vec<nn<T>>, input: &Vec<Vec<Array2<T>>>, loss: L) -> GradCheck<T>
+where L: Fn(&Vec<Vec<Array2<T>>>) -> (T, Vec<Vec<Array2<T>>>) {
+    // the step minimizing truncation + rounding error of central differences
+    let h = T::epsilon().cbrt();
+    let value = |x: &Vec<Vec<Array2<T>>>| loss(forward(network, x).last().unwrap()).0;
+
+    // analytic
+    let mut outputs = forward(network, input);
+    let (_, mut deltas) = loss(&outputs.pop().unwrap());
+    let mut gradients = vec![];
+    for (layer, layer_input) in network.iter().zip(outputs.iter()).rev() {
+        let (next_deltas, gradient) = layer.gradients(layer_input, deltas);
+        deltas = next_deltas;
+        gradients.push(gradient);
+    }
+    gradients.reverse();
+
+    // numeric, input
+    let mut perturbed = input.clone();
+    let mut input_error = T::zero();
+    for s in 0..input.len() {
+        for c in 0..input[s].len() {
+            for (r, col) in indices(input[s][c].dim()) {
+                let original = input[s][c][[r, col]];
+                perturbed[s][c][[r, col]] = original + h;
+                let plus = value(&perturbed);
+                perturbed[s][c][[r, col]] = original - h;
+                let minus = value(&perturbed);
+                perturbed[s][c][[r, col]] = original;
+
+                input_error = input_error.max(error(deltas[s][c][[r, col]], (plus - minus) / (h + h)));
+            }
+        }
+    }
+
+    // numeric, parameters
+    let mut parameter_errors = vec![];
+    for (l, (layer, gradient)) in network.iter().zip(gradients.iter()).enumerate() {
+        let mut parameters = layer.parameters();
+
+        for tensor in 0..parameters.len() {
+            let mut tensor_error = T::zero();
+            for (r, col) in indices(parameters[tensor].dim()) {
+                let original = parameters[tensor][[r, col]];
+                parameters[tensor][[r, col]] = original + h;
+                layer.set_parameters(&parameters);
+                let plus = value(input);
+                parameters[tensor][[r, col]] = original - h;
+                layer.set_parameters(&parameters);
+                let minus = value(input);
+                parameters[tensor][[r, col]] = original;
+                layer.set_parameters(&parameters);
+
+                tensor_error = tensor_error.max(error(gradient.derivates[tensor][[r, col]], (plus - minus) / (h + h)));
+            }
+            parameter_errors.push(GradError { layer: l, tensor, error: tensor_error });
+        }
+    }
+
+    GradCheck {
+        input: input_error,
+        parameters: parameter_errors
+    }
+}
+
+// losses for gradcheck
+
+// 0.5 * sum((output - target)^2), for any layout of the output
+pub fn squared_error<T: Float>(output: &[Vec<Array2<T>>], target: &[Vec<Array2<T>>]) -> (T, Vec<Vec<Array2<T>>>) {
+    let deltas = output.iter().zip(target.iter()).map(|(o, t)| {
+        o.iter().zip(t.iter()).map(|(a, b)| a - b).collect::<Vec<Array2<T>>>()
+    }).collect::<Vec<Vec<Array2<T>>>>();
+    let loss = deltas.iter().flatten().fold(T::zero(), |acc, d| acc + d.iter().fold(T::zero(), |a, &x| a + x * x));
+
+    (loss * T::from_f32(0.5), deltas)
+}
+
+// cross entropy of a softmax output [1, 1, sample, classes], summed over samples
+// as in train, the derivative is taken at the input of the softmax layer: output - labels
+pub fn cross_entropy<T: Float>(output: &[Vec<Array2<T>>], labels: &Array2<T>) -> (T, Vec<Vec<Array2<T>>>) {
+    let loss = output[0][0].iter().zip(labels.iter()).fold(T::zero(), |acc, (&o, &l)| acc - l * o.ln());
+    (loss, vec![vec![&output[0][0] - labels]])
+}
+
+fn error<T: Float>(analytic: T, numeric: T) -> T {
+    (analytic - numeric).abs() / analytic.abs().max(numeric.abs()).max(T::one())
+}
@@ -0,0 +1,313 @@
+use crate::network::nn;
+use crate::merge::Merge;
+use crate::utils::sum_nested_vector;
+use crate::utils::utils::{compute_loss, evaluate};
+
+use crate::dataset::Normalize;
+use crate::trained::graph::GraphJson;
+use crate::trained::{Convert, LoadError};
+
+use ndarray::Array2;
+use crate::float::Float;
+use crate::random;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+// a node is either a single-input layer or a multi-input merge layer
+pub enum Op<T = f32> {
+    Layer(nn<T>),
+    Merge(Merge)
+}
+
+pub struct Node<T = f32> {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub op: Op<T>
+}
+
+// directed acyclic graph of named nodes
+// input: the name used by nodes to refer to the input of the graph
+// output: the name of the node whose output is the output of the graph
+// seed: the seed of random when the graph was built, None for graphs saved before it was recorded
+// normalize: applied to the input by forward and infer, so training and inference preprocess the same way
+pub struct Graph<T = f32> {
+    pub input: String,
+    pub output: String,
+    pub nodes: Vec<Node<T>>,
+    pub seed: Option<u64>,
+    pub normalize: Option<Normalize>
+}
+
+impl<T: Float> Graph<T> {
+    pub fn new(input: &str, output: &str) -> Graph<T> {
+        Graph {
+            input: input.to_string(),
+            output: output.to_string(),
+            nodes: vec![],
+            seed: Some(random::seed()),
+            normalize: None
+        }
+    }
+
+    pub fn add_layer(&mut self, name: &str, layer: nn<T>, input: &str) -> &mut Graph<T> {
+        self.add_node(name, Op::Layer(layer), vec![input])
+    }
+
+    pub fn add_merge(&mut self, name: &str, merge: Merge, inputs: Vec<&str>) -> &mut Graph<T> {
+        assert!(inputs.len() > 1, "merge node {} needs at least two inputs", name);
+        self.add_node(name, Op::Merge(merge), inputs)
+    }
+
+    fn add_node(&mut self, name: &str, op: Op<T>, inputs: Vec<&str>) -> &mut Graph<T> {
+        if name == self.input || self.nodes.iter().any(|node| node.name == name) {
+            panic!("duplicated node name {}", name)
+        }
+
+        self.nodes.push(Node {
+            name: name.to_string(),
+            inputs: inputs.into_iter().map(|input| input.to_string()).collect(),
+            op
+        });
+        self
+    }
+
+    pub fn order(&self) -> Vec<usize> {
+        // topological order of the nodes (Kahn's algorithm)
+        // panics if a node refers to an unknown node or the graph has a cycle
+        self.try_order().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_order(&self) -> Result<Vec<usize>, String> {
+        // same as order, the error describes why there is no order
+        let index: HashMap<&str, usize> = self.nodes.iter().enumerate()
+            .map(|(i, node)| (node.name.as_str(), i))
+            .collect();
+
+        let mut in_degree: Vec<usize> = vec![0; self.nodes.len()];
+        let mut consumers: Vec<Vec<usize>> = vec![vec![]; self.nodes.len()];
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            for input in node.inputs.iter() {
+                if *input == self.input {
+                    continue;
+                }
+                match index.get(input.as_str()) {
+                    Some(&j) => {
+                        in_degree[i] += 1;
+                        consumers[j].push(i);
+                    },
+                    None => return Err(format!("node {} refers to unknown node {}", node.name, input)),
+                }
+            }
+        }
+
+        let mut ready: Vec<usize> = (0..self.nodes.len()).filter(|&i| in_degree[i] == 0).rev().collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        while let Some(i) = ready.pop() {
+            order.push(i);
+            for &consumer in consumers[i].iter().rev() {
+                in_degree[consumer] -= 1;
+                if in_degree[consumer] == 0 {
+                    ready.push(consumer);
+                }
+            }
+        }
+
+        if order.len() != self.nodes.len() {
+            return Err("graph has a cycle".to_string())
+        }
+        Ok(order)
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        // what add_layer, add_merge and order would panic on, for graphs that were not built with them (loaded graphs)
+        let mut names: Vec<&str> = vec![self.input.as_str()];
+        for node in self.nodes.iter() {
+            if names.contains(&node.name.as_str()) {
+                return Err(format!("duplicated node name {}", node.name))
+            }
+            names.push(node.name.as_str());
+
+            match node.op {
+                Op::Layer(_) if node.inputs.len() != 1 => {
+                    return Err(format!("layer node {} needs one input, got {}", node.name, node.inputs.len()))
+                },
+                Op::Merge(_) if node.inputs.len() < 2 => {
+                    return Err(format!("merge node {} needs at least two inputs", node.name))
+                },
+                _ => (),
+            }
+        }
+        if !names.contains(&self.output.as_str()) {
+            return Err(format!("unknown output node {}", self.output))
+        }
+        self.try_order().map(|_| ())
+    }
+
+    pub fn to_string(self) -> String {
+        GraphJson::new(self).to_string()
+    }
+}
+
+pub fn forward<T: Float>(graph: &Graph<T>, input: &Vec<Vec<Array2<T>>>) -> HashMap<String, Vec<Vec<Array2<T>>>> {
+    // return the output of every node, the input is stored with the name graph.input
+    let mut outputs: HashMap<String, Vec<Vec<Array2<T>>>> = HashMap::new();
+    let input = match &graph.normalize {
+        Some(normalize) => normalize.normalize(input),
+        None => input.clone(),
+    };
+    outputs.insert(graph.input.clone(), input);
+
+    for i in graph.order() {
+        let node = &graph.nodes[i];
+        let output = match &node.op {
+            Op::Layer(layer) => layer.forward(&outputs[&node.inputs[0]]),
+            Op::Merge(merge) => {
+                let inputs = node.inputs.iter().map(|name| &outputs[name]).collect::<Vec<&Vec<Vec<Array2<T>>>>>();
+                merge.forward(&inputs)
+            },
+        };
+        outputs.insert(node.name.clone(), output);
+    }
+    outputs
+}
+
+pub fn infer<T: Float>(graph: &Graph<T>, input: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+    // inference only: return the output of the graph without keeping the output of every node
+    // the input is borrowed, the output of a node is dropped once every node using it has run
+    let normalized = graph.normalize.as_ref().map(|normalize| normalize.normalize(input));
+    let input = normalized.as_ref().unwrap_or(input);
+
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for node in graph.nodes.iter() {
+        for name in node.inputs.iter() {
+            *uses.entry(name.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let mut outputs: HashMap<&str, Vec<Vec<Array2<T>>>> = HashMap::new();
+    for i in graph.order() {
+        let node = &graph.nodes[i];
+        let output = {
+            let inputs = node.inputs.iter().map(|name| {
+                if *name == graph.input { input } else { &outputs[name.as_str()] }
+            }).collect::<Vec<&Vec<Vec<Array2<T>>>>>();
+
+            match &node.op {
+                Op::Layer(layer) => layer.infer(inputs[0]),
+                Op::Merge(merge) => merge.forward(&inputs),
+            }
+        };
+
+        for name in node.inputs.iter() {
+            let count = uses.get_mut(name.as_str()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                outputs.remove(name.as_str());
+            }
+        }
+        // nodes that do not reach the output are dropped right away
+        if uses.contains_key(node.name.as_str()) || node.name == graph.output {
+            outputs.insert(node.name.as_str(), output);
+        }
+    }
+
+    match outputs.remove(graph.output.as_str()) {
+        Some(output) => output,
+        None => input.clone(), // the output of the graph is its input
+    }
+}
+
+pub fn backward<T: Float>(graph: &mut Graph<T>, outputs: HashMap<String, Vec<Vec<Array2<T>>>>, output: Vec<Vec<Array2<T>>>) {
+    // outputs: the result of forward
+    // output: the delta of the graph output
+    // when a node feeds several nodes, its delta is the sum of the deltas from every consumer
+    let mut deltas: HashMap<String, Vec<Vec<Array2<T>>>> = HashMap::new();
+    deltas.insert(graph.output.clone(), output);
+
+    for i in graph.order().into_iter().rev() {
+        let node = &graph.nodes[i];
+        let delta = match deltas.remove(&node.name) {
+            Some(delta) => delta,
+            None => continue, // this node does not reach the output
+        };
+
+        let input_deltas = match &node.op {
+            Op::Layer(layer) => vec![layer.backward(&outputs[&node.inputs[0]], delta)],
+            Op::Merge(merge) => {
+                let inputs = node.inputs.iter().map(|name| &outputs[name]).collect::<Vec<&Vec<Vec<Array2<T>>>>>();
+                merge.backward(&inputs, delta)
+            },
+        };
+
+        for (name, input_delta) in node.inputs.iter().zip(input_deltas.into_iter()) {
+            let accumulated = match deltas.remove(name) {
+                Some(acc) => acc.into_iter().zip(input_delta.into_iter()).map(|(a, b)| {
+                    sum_nested_vector(a, b)
+                }).collect::<Vec<Vec<Array2<T>>>>(),
+                None => input_delta,
+            };
+            deltas.insert(name.clone(), accumulated);
+        }
+    }
+}
+
+pub fn predict<T: Float>(graph: &Graph<T>, test_inputs: &Vec<Vec<Array2<T>>>, target: &Array2<T>) -> T {
+    let samples = test_inputs.len();
+    let output = infer(graph, test_inputs); // [1, 1, sample * 10]
+
+    evaluate(&output[0][0], target) / T::from_usize(samples)
+}
+
+pub fn train_one_by_one<T: Float>(
+    graph: &mut Graph<T>,
+    epochs: usize,
+    inputs: Vec<Vec<Array2<T>>>,
+    target: Array2<T>
+) {
+    let samples = T::from_usize(inputs.len());
+    let classes = target.shape()[1];
+
+    for epoch in 0..epochs {
+        println!("******************************************");
+        println!("Starting #{:?}# Epoch...", epoch);
+
+        let mut correct = T::zero();
+        let mut loss = T::zero();
+
+        for (i, input) in inputs.iter().enumerate() {
+            let outputs = forward(graph, &vec![input.clone()]);
+            let final_output = &outputs[&graph.output];
+
+            let label = target.row(i).to_owned().into_shape((1, classes)).unwrap();
+            loss += compute_loss(&final_output[0][0], &label);
+            correct += evaluate(&final_output[0][0], &label);
+
+            let deltas = vec![vec![final_output[0][0].clone() - &label]];
+            backward(graph, outputs, deltas);
+        }
+
+        let train_accuracy = correct / samples;
+        println!("Epoch#{:?}# Train-Acc: {:?} loss: {:?}", epoch, train_accuracy, loss);
+    }
+}
+
+pub fn save<T: Float>(graph: Graph<T>, path: &str) {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(Path::new(path))
+        .unwrap();
+
+    file.write_all(graph.to_string().as_bytes()).expect("failed to save the graph");
+}
+
+pub fn load<T: Float>(path: &str) -> Result<Graph<T>, LoadError> {
+    let text = fs::read_to_string(Path::new(path))?;
+    let graph: GraphJson<T> = serde_json::from_str(&text)?;
+    graph.to_layer()
+}
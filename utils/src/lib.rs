@@ -3,10 +3,21 @@ use ndarray::Array2;
 pub mod utils;
 pub mod convolution;
 pub mod pooling;
+pub mod upsampling;
 pub mod full_connected;
 pub mod activation;
+pub mod normalization;
 pub mod network;
+pub mod merge;
+pub mod graph;
 pub mod trained;
 pub mod propagation;
+pub mod float;
+pub mod gradcheck;
+pub mod linalg;
+pub mod parallel;
+pub mod random;
+pub mod workspace;
 
-pub mod dataset;
\ No newline at end of file
+pub mod dataset;
+pub mod validation;
\ No newline at end of file
@@ -0,0 +1,111 @@
+use ndarray::{ArrayBase, Array2, ArrayView2, ArrayViewMut2, Data, DataMut, Ix2};
+use ndarray::linalg::general_mat_mul;
+use crate::float::Float;
+
+// matrix products of the conv and full layers
+// with the `blas` feature, f32/f64 products are computed by cblas_sgemm/cblas_dgemm of the system BLAS (OpenBLAS)
+// without it, or when a matrix layout can not be passed to BLAS, the pure Rust GEMM of ndarray is used
+
+pub fn dot<T: Float, S1, S2>(a: &ArrayBase<S1, Ix2>, b: &ArrayBase<S2, Ix2>) -> Array2<T>
+where S1: Data<Elem = T>, S2: Data<Elem = T> {
+    // a [m, k] * b [k, n] = [m, n]
+    let mut c = Array2::zeros((a.shape()[0], b.shape()[1]));
+    gemm(T::one(), a, b, T::zero(), &mut c);
+    c
+}
+
+pub fn gemm<T: Float, S1, S2, S3>(alpha: T, a: &ArrayBase<S1, Ix2>, b: &ArrayBase<S2, Ix2>, beta: T, c: &mut ArrayBase<S3, Ix2>)
+where S1: Data<Elem = T>, S2: Data<Elem = T>, S3: DataMut<Elem = T> {
+    // c = alpha * a * b + beta * c
+    assert!(
+        a.shape()[1] == b.shape()[0] && c.shape()[0] == a.shape()[0] && c.shape()[1] == b.shape()[1],
+        "can not multiply {:?} by {:?} into {:?}", a.shape(), b.shape(), c.shape()
+    );
+    T::gemm(alpha, &a.view(), &b.view(), beta, &mut c.view_mut());
+}
+
+pub fn sgemm(alpha: f32, a: &ArrayView2<f32>, b: &ArrayView2<f32>, beta: f32, c: &mut ArrayViewMut2<f32>) {
+    #[cfg(feature = "blas")]
+    {
+        if blas::sgemm(alpha, a, b, beta, c) {
+            return;
+        }
+    }
+    general_mat_mul(alpha, a, b, beta, c)
+}
+
+pub fn dgemm(alpha: f64, a: &ArrayView2<f64>, b: &ArrayView2<f64>, beta: f64, c: &mut ArrayViewMut2<f64>) {
+    #[cfg(feature = "blas")]
+    {
+        if blas::dgemm(alpha, a, b, beta, c) {
+            return;
+        }
+    }
+    general_mat_mul(alpha, a, b, beta, c)
+}
+
+#[cfg(feature = "blas")]
+mod blas {
+    use ndarray::{ArrayView2, ArrayViewMut2};
+    use std::os::raw::c_int;
+
+    const ROW_MAJOR: c_int = 101;
+    const NO_TRANS: c_int = 111;
+    const TRANS: c_int = 112;
+
+    #[link(name = "openblas")]
+    extern "C" {
+        fn cblas_sgemm(layout: c_int, transa: c_int, transb: c_int, m: c_int, n: c_int, k: c_int,
+            alpha: f32, a: *const f32, lda: c_int, b: *const f32, ldb: c_int,
+            beta: f32, c: *mut f32, ldc: c_int);
+
+        fn cblas_dgemm(layout: c_int, transa: c_int, transb: c_int, m: c_int, n: c_int, k: c_int,
+            alpha: f64, a: *const f64, lda: c_int, b: *const f64, ldb: c_int,
+            beta: f64, c: *mut f64, ldc: c_int);
+    }
+
+    fn layout(shape: &[usize], strides: &[isize]) -> Option<(c_int, c_int)> {
+        // (transpose, leading dimension) of a row major matrix, or of the transpose of a row major matrix
+        // None for the layouts BLAS can not read (e.g. non-unit strides in both axes)
+        let (rows, cols) = (shape[0] as isize, shape[1] as isize);
+
+        if strides[1] == 1 && strides[0] >= cols.max(1) {
+            Some((NO_TRANS, strides[0] as c_int))
+        } else if strides[0] == 1 && strides[1] >= rows.max(1) {
+            Some((TRANS, strides[1] as c_int))
+        } else {
+            None
+        }
+    }
+
+    macro_rules! blas_gemm {
+        ($name: ident, $cblas: ident, $t: ty) => {
+            // return false when the product has to be computed by the fallback
+            pub fn $name(alpha: $t, a: &ArrayView2<$t>, b: &ArrayView2<$t>, beta: $t, c: &mut ArrayViewMut2<$t>) -> bool {
+                let (m, k, n) = (a.shape()[0], a.shape()[1], b.shape()[1]);
+                if m == 0 || n == 0 || k == 0 || [m, n, k].iter().any(|&d| d > c_int::MAX as usize) {
+                    return false;
+                }
+
+                let (a_layout, b_layout) = match (layout(a.shape(), a.strides()), layout(b.shape(), b.strides())) {
+                    (Some(a_layout), Some(b_layout)) => (a_layout, b_layout),
+                    _ => return false,
+                };
+                let c_layout = match layout(c.shape(), c.strides()) {
+                    Some((NO_TRANS, ldc)) => ldc,
+                    _ => return false,
+                };
+
+                unsafe {
+                    $cblas(ROW_MAJOR, a_layout.0, b_layout.0, m as c_int, n as c_int, k as c_int,
+                        alpha, a.as_ptr(), a_layout.1, b.as_ptr(), b_layout.1,
+                        beta, c.as_mut_ptr(), c_layout);
+                }
+                true
+            }
+        };
+    }
+
+    blas_gemm!(sgemm, cblas_sgemm, f32);
+    blas_gemm!(dgemm, cblas_dgemm, f64);
+}
@@ -0,0 +1,96 @@
+use ndarray::Array2;
+use crate::float::Float;
+
+// merge layers combine the outputs of several nodes of a graph
+// inputs [node, sample, channel, width, width]
+// Add and Multiply need every input to have the same shape
+// Concat stacks the channels of every input: [sample, channel_1 + channel_2 + ..., width, width]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Merge {
+    Add,
+    Concat,
+    Multiply
+}
+
+impl Merge {
+    pub fn new(name: String) -> Merge {
+        match Merge::from_name(&name) {
+            Some(merge) => merge,
+            None => panic!("unknown merge layer {}", name),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Merge> {
+        // the inverse of name, None for an unknown name (e.g. a corrupt saved graph)
+        match name {
+            "Add" => Some(Merge::Add),
+            "Concat" => Some(Merge::Concat),
+            "Multiply" => Some(Merge::Multiply),
+            _ => None,
+        }
+    }
+
+    pub fn forward<T: Float>(&self, inputs: &[&Vec<Vec<Array2<T>>>]) -> Vec<Vec<Array2<T>>> {
+        let mut input_iter = inputs.iter();
+        let first = (*input_iter.next().unwrap()).clone();
+
+        match self {
+            Merge::Add => input_iter.fold(first, |acc, input| {
+                combine(acc, input, |a, b| a + b)
+            }),
+            Merge::Multiply => input_iter.fold(first, |acc, input| {
+                combine(acc, input, |a, b| a * b)
+            }),
+            Merge::Concat => input_iter.fold(first, |mut acc, input| {
+                for (sample, channels) in acc.iter_mut().zip(input.iter()) {
+                    sample.extend(channels.iter().cloned());
+                }
+                acc
+            }),
+        }
+    }
+
+    pub fn backward<T: Float>(
+        &self,
+        inputs: &[&Vec<Vec<Array2<T>>>],
+        deltas: Vec<Vec<Array2<T>>>
+    ) -> Vec<Vec<Vec<Array2<T>>>> {
+        // return one delta for every input, in the same order as inputs
+
+        match self {
+            Merge::Add => inputs.iter().map(|_| deltas.clone()).collect(),
+            Merge::Multiply => (0..inputs.len()).map(|index| {
+                // d(x_1 * x_2 * ... ) / d(x_i) = product of the other inputs
+                inputs.iter().enumerate()
+                    .filter(|(i, _)| *i != index)
+                    .fold(deltas.clone(), |acc, (_, input)| combine(acc, input, |a, b| a * b))
+            }).collect(),
+            Merge::Concat => {
+                let mut offset = 0;
+                inputs.iter().map(|input| {
+                    let channels = input[0].len();
+                    let delta = deltas.iter().map(|sample| {
+                        sample[offset..offset + channels].to_vec()
+                    }).collect::<Vec<Vec<Array2<T>>>>();
+                    offset += channels;
+                    delta
+                }).collect()
+            },
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Merge::Add => "Add".to_string(),
+            Merge::Concat => "Concat".to_string(),
+            Merge::Multiply => "Multiply".to_string(),
+        }
+    }
+}
+
+fn combine<T: Float, F>(a: Vec<Vec<Array2<T>>>, b: &[Vec<Array2<T>>], op: F) -> Vec<Vec<Array2<T>>>
+where F: Fn(Array2<T>, &Array2<T>) -> Array2<T> {
+    a.into_iter().zip(b.iter()).map(|(x, y)| {
+        x.into_iter().zip(y.iter()).map(|(i, j)| op(i, j)).collect::<Vec<Array2<T>>>()
+    }).collect::<Vec<Vec<Array2<T>>>>()
+}
@@ -1,18 +1,25 @@
-use crate::propagation::Propagation;
-use crate::convolution::Conv3D;
+use crate::propagation::{Propagation, Gradients};
+use crate::convolution::{Conv3D, ConvConfig, ConvTranspose2D, SeparableConv};
 use crate::pooling::Pool;
+use crate::upsampling::Upsample;
 use crate::full_connected::FullLayer;
 use crate::activation::Activation;
+use crate::normalization::{LayerNorm, GroupNorm};
 use crate::utils::utils::{compute_loss, evaluate};
+use crate::workspace::{Workspace, Scratch, assign};
 
-use crate::trained::{convolution, pooling, activation, full_connected, Convert};
+use crate::dataset::{DataLoader, Dataset, DatasetError, Normalize};
+use crate::trained::{convolution, pooling, upsampling, activation, full_connected, normalization, Convert, LoadError};
+use crate::trained::network::CheckpointJson;
 
 use ndarray::Array2;
+use crate::float::Float;
+use crate::random;
 use std::fmt::{self, Formatter};
 
 use serde::{Serialize, Deserialize, Deserializer};
 
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::Path;
 use std::fmt::Debug;
@@ -31,75 +38,138 @@ macro_rules! timing {
     };
 }
 
-pub enum nn {
-    Conv(Conv3D),
+pub enum nn<T = f32> {
+    Conv(Conv3D<T>),
+    ConvTranspose(ConvTranspose2D<T>),
+    Separable(SeparableConv<T>),
     Pool(Pool),
+    Upsample(Upsample<T>),
     Activation(Activation),
-    Full(FullLayer)
+    Full(FullLayer<T>),
+    LayerNorm(LayerNorm<T>),
+    GroupNorm(GroupNorm<T>)
 }
 
-impl nn {
-    pub fn new(name: String, config: Vec<usize>, alpha: f32) -> nn {
+impl<T: Float> nn<T> {
+    pub fn new(name: String, config: Vec<usize>, alpha: T) -> nn<T> {
         
         if name == "Conv" {
-            nn::Conv(Conv3D::new(config[0], config[1], config[2], config[3], config[4], config[5], alpha, config[6]))
+            // config[7] is the optional dilation, config[8] is the optional groups
+            let dilation = config.get(7).copied().unwrap_or(1);
+            let groups = config.get(8).copied().unwrap_or(1);
+            nn::Conv(Conv3D::new(ConvConfig::new(config[0], config[1], config[2], config[3], config[4], config[5])
+                .dilation(dilation).groups(groups).boundary(config[6]), alpha))
+        } else if name == "Separable" {
+            let dilation = config.get(7).copied().unwrap_or(1);
+            nn::Separable(SeparableConv::new(ConvConfig::new(config[0], config[1], config[2], config[3], config[4], config[5])
+                .dilation(dilation).boundary(config[6]), alpha))
+        } else if name == "ConvTranspose" {
+            nn::ConvTranspose(ConvTranspose2D::new(config[0], config[1], config[2], config[3], config[4], config[5], config[6], alpha))
         } else if name == "Pool" {
             nn::Pool(Pool::new(config[0], config[1], config[2], config[3], config[4], config[5]))
+        } else if name == "Upsample" {
+            nn::Upsample(Upsample::new(config[0], config[1], config[2]))
         } else if name == "Full" {
             nn::Full(FullLayer::new(config[0], config[1], alpha, config[2]))
+        } else if name == "LayerNorm" {
+            nn::LayerNorm(LayerNorm::new(config[0], alpha, config[1]))
+        } else if name == "GroupNorm" {
+            nn::GroupNorm(GroupNorm::new(config[0], config[1], alpha, config[2]))
         } else {
             nn::Activation(Activation::new(config[0]))
         }
     }
 
-    pub fn forward(&self, input: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
-        // inputs [sample, in_channel, width, width]
-        // output [sample, out_channel, out_width, out_width]
-        
+    pub fn layer(&self) -> &dyn Propagation<T> {
         match self {
-            Self::Conv(conv) => conv.forward(input),
-            Self::Pool(p) => p.forward(input),
-            Self::Activation(a) => a.forward(input),
-            Self::Full(f) => f.forward(input),
+            Self::Conv(conv) => conv,
+            Self::ConvTranspose(conv) => conv,
+            Self::Separable(conv) => conv,
+            Self::Pool(p) => p,
+            Self::Upsample(u) => u,
+            Self::Activation(a) => a,
+            Self::Full(f) => f,
+            Self::LayerNorm(n) => n,
+            Self::GroupNorm(n) => n,
         }
     }
 
+    pub fn forward(&self, input: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        // inputs [sample, in_channel, width, width]
+        // output [sample, out_channel, out_width, out_width]
+        self.layer().forward(input)
+    }
+
+    pub fn infer(&self, input: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        self.layer().infer(input)
+    }
+
+    pub fn forward_into(&self, input: &Vec<Vec<Array2<T>>>, output: &mut Vec<Vec<Array2<T>>>, scratch: &mut Scratch<T>) {
+        self.layer().forward_into(input, output, scratch)
+    }
+
+    pub fn backward(&self, input: &Vec<Vec<Array2<T>>>, deltas: Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        // input: the input of this layer in forward
+        // deltas [sample, out_channel, out_width, out_width]
+        self.layer().backward(input, deltas)
+    }
+
+    pub fn gradients(&self, input: &Vec<Vec<Array2<T>>>, deltas: Vec<Vec<Array2<T>>>) -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        self.layer().gradients(input, deltas)
+    }
+
+    pub fn apply(&self, gradients: &Gradients<T>) {
+        self.layer().apply(gradients)
+    }
+
+    pub fn parameters(&self) -> Vec<Array2<T>> {
+        self.layer().parameters()
+    }
+
+    pub fn set_parameters(&self, parameters: &[Array2<T>]) {
+        self.layer().set_parameters(parameters)
+    }
+
     pub fn to_string(self) -> String {
 
         match self {
             Self::Conv(conv) => convolution::Conv3DJson::new(conv).to_string(),
+            Self::ConvTranspose(conv) => convolution::ConvTranspose2DJson::new(conv).to_string(),
+            Self::Separable(conv) => convolution::SeparableConvJson::new(conv).to_string(),
             Self::Pool(p) => pooling::PoolJson::new(p).to_string(),
+            Self::Upsample(u) => upsampling::UpsampleJson::new(u).to_string(),
             Self::Activation(a) => activation::ActivationJson::new(a).to_string(),
             Self::Full(f) => full_connected::FullJson::new(f).to_string(),
+            Self::LayerNorm(n) => normalization::LayerNormJson::new(n).to_string(),
+            Self::GroupNorm(n) => normalization::GroupNormJson::new(n).to_string(),
         }
     }
-
-    // pub fn convert(&self) -> Box<dyn Propagation> {
-
-    //     match self {
-    //         Self::Conv(conv) => Box::new(*conv),
-    //         Self::Activation(a) => Box::new(*a),
-    //         Self::Full(f) => Box::new(*f),
-    //         Self::Pool(p) => Box::new(*p),
-    //     }
-
-    // }
 }
 
-impl fmt::Display for nn {
+impl<T: Float> fmt::Display for nn<T> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-
-        let empty = Conv3D::new(1,1,1,1,28,2, 0.1,0);
-        let text = match self {
-            nn::Conv(conv) => conv,
-            _ => &empty,
-        };
-
-        write!(
-            f,
-            "layer {}",
-            text
-        )
+        // the kind and shape of the layer, read from its fields: building a layer here would draw from random
+        match self {
+            nn::Conv(conv) => write!(f, "layer {}", conv),
+            nn::ConvTranspose(conv) => write!(
+                f, "layer ConvTranspose2D {} -> {} channels, {}x{} filters, width {} -> {}",
+                conv.in_channel, conv.out_channel, conv.filter_width, conv.filter_width, conv.prev_width, conv.output_width
+            ),
+            nn::Separable(conv) => write!(
+                f, "layer SeparableConv {} -> {} channels, {}x{} filters, width {} -> {}",
+                conv.depthwise.in_channel, conv.pointwise.out_channel, conv.depthwise.filter_width,
+                conv.depthwise.filter_width, conv.depthwise.prev_width, conv.pointwise.output_width
+            ),
+            nn::Pool(pool) => write!(
+                f, "layer Pool {}x{} stride {}, {} channels of width {}",
+                pool.width, pool.width, pool.stride, pool.out_channel, pool.input_width
+            ),
+            nn::Upsample(upsample) => write!(f, "layer Upsample x{}, width {}", upsample.scale, upsample.input_width),
+            nn::Activation(activation) => write!(f, "layer Activation {}", activation.end),
+            nn::Full(full) => write!(f, "layer Full {} -> {} neurons", full.prev_neurons, full.neurons),
+            nn::LayerNorm(norm) => write!(f, "layer LayerNorm {} channels", norm.norm.channels),
+            nn::GroupNorm(norm) => write!(f, "layer GroupNorm {} groups of {} channels", norm.groups, norm.channels),
+        }
     }
 }
 
@@ -124,27 +194,27 @@ impl NetworkGraph {
         serde_json::to_string(&self).unwrap()
     }
 
-    pub fn to_layer(self) -> Vec<nn> {
+    pub fn to_layer(self) -> Result<Vec<nn>, LoadError> {
         let mut network: Vec<nn> = vec![];
 
-        network.push(nn::Conv(self.conv1.to_layer()));
-        network.push(nn::Activation(self.activation1.to_layer()));
-        network.push(nn::Pool(self.pool1.to_layer()));
+        network.push(nn::Conv(self.conv1.to_layer()?));
+        network.push(nn::Activation(self.activation1.to_layer()?));
+        network.push(nn::Pool(self.pool1.to_layer()?));
         
-        network.push(nn::Conv(self.conv2.to_layer()));
-        network.push(nn::Activation(self.activation2.to_layer()));
-        network.push(nn::Pool(self.pool2.to_layer()));
+        network.push(nn::Conv(self.conv2.to_layer()?));
+        network.push(nn::Activation(self.activation2.to_layer()?));
+        network.push(nn::Pool(self.pool2.to_layer()?));
 
-        network.push(nn::Full(self.full1.to_layer()));
-        network.push(nn::Activation(self.activation3.to_layer()));
-        network.push(nn::Full(self.full2.to_layer()));
-        network.push(nn::Activation(self.activation.to_layer()));
+        network.push(nn::Full(self.full1.to_layer()?));
+        network.push(nn::Activation(self.activation3.to_layer()?));
+        network.push(nn::Full(self.full2.to_layer()?));
+        network.push(nn::Activation(self.activation.to_layer()?));
 
-        network
+        Ok(network)
     }
 }
 
-pub fn forward(network: &Vec<nn>, input: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Vec<Array2<f32>>>> {
+pub fn forward<T: Float>(network: &Vec<nn<T>>, input: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Vec<Array2<T>>>> {
     let mut outputs = vec![input.clone()];
 
     for layer in network.iter() {
@@ -154,107 +224,256 @@ pub fn forward(network: &Vec<nn>, input: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Vec<
     outputs
 }
 
+pub fn forward_with<'a, T: Float>(
+    network: &Vec<nn<T>>,
+    input: &[Vec<Array2<T>>],
+    workspace: &'a mut Workspace<T>
+) -> &'a Vec<Vec<Array2<T>>> {
+    // same as forward, but the activations are written into the buffers of workspace
+    // return the output of the network (workspace.activations[network.len()])
+    assign(&mut workspace.activations[0], input);
+
+    for (i, layer) in network.iter().enumerate() {
+        let (inputs, outputs) = workspace.activations.split_at_mut(i + 1);
+        layer.forward_into(&inputs[i], &mut outputs[0], &mut workspace.scratch[i]);
+    }
+    workspace.output()
+}
+
+pub fn infer<T: Float>(network: &Vec<nn<T>>, input: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+    // inference only: the input is not cloned and every activation is dropped
+    // as soon as the next layer has used it, only the final output is returned
+    match network.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.infer(input), |output, layer| layer.infer(&output)),
+        None => input.clone(),
+    }
+}
 
-pub fn backward(network:&mut Vec<nn>, inputs: Vec<Vec<Vec<Array2<f32>>>>, output: Vec<Vec<Array2<f32>>>) {
+pub fn backward<T: Float>(network:&mut Vec<nn<T>>, inputs: Vec<Vec<Vec<Array2<T>>>>, output: Vec<Vec<Array2<T>>>) {
     // inputs = outputs [0:-1]
-    let mut deltas: Vec<Vec<Array2<f32>>> = output;
+    let mut deltas: Vec<Vec<Array2<T>>> = output;
     for (layer, input) in network.iter_mut().zip(inputs.into_iter()).rev() {
-        
-        deltas = match layer {
-            nn::Conv(conv) => conv.backward(input, deltas),
-            nn::Pool(p) => p.backward(input, deltas),
-            nn::Activation(a) => a.backward(input, deltas),
-            nn::Full(f) => f.backward(input, deltas),
-        };
-        
+        deltas = layer.backward(&input, deltas);
+    }
+}
+
+pub fn gradients<T: Float>(network: &Vec<nn<T>>, inputs: Vec<Vec<Vec<Array2<T>>>>, output: Vec<Vec<Array2<T>>>) -> Vec<Gradients<T>> {
+    // same as backward, but return the gradients of every layer instead of updating the layers
+    // gradients[i] belongs to network[i]
+    let mut deltas: Vec<Vec<Array2<T>>> = output;
+    let mut gradients: Vec<Gradients<T>> = vec![];
+
+    for (layer, input) in network.iter().zip(inputs).rev() {
+        let (next_deltas, gradient) = layer.gradients(&input, deltas);
+        deltas = next_deltas;
+        gradients.push(gradient);
+    }
+    gradients.reverse();
+    gradients
+}
+
+pub fn gradients_with<T: Float>(network: &Vec<nn<T>>, workspace: &mut Workspace<T>, output: Vec<Vec<Array2<T>>>) {
+    // same as gradients, the inputs of the layers are the activations of forward_with
+    // workspace.gradients[i] is replaced by the gradients of network[i]
+    // nothing is cloned: the activations are read in place and the deltas are moved from layer to layer
+    let mut deltas: Vec<Vec<Array2<T>>> = output;
+
+    for (i, layer) in network.iter().enumerate().rev() {
+        let (next_deltas, gradient) = layer.gradients(&workspace.activations[i], deltas);
+        deltas = next_deltas;
+        workspace.gradients[i] = gradient;
+    }
+}
+
+pub fn apply<T: Float>(network: &mut Vec<nn<T>>, gradients: &Vec<Gradients<T>>) {
+    for (layer, gradient) in network.iter().zip(gradients.iter()) {
+        layer.apply(gradient);
+    }
+}
+
+// the layers of a network and the seed of random when they were built,
+// so the run that produced them can be replayed with set_seed
+// normalize: the preprocessing the network was trained with, given to the train functions,
+// Checkpoint::infer and Checkpoint::predict apply it so the raw inputs are preprocessed the same way
+pub struct Checkpoint<T = f32> {
+    pub network: Vec<nn<T>>,
+    pub seed: Option<u64>,
+    pub normalize: Option<Normalize>
+}
+
+impl<T: Float> Checkpoint<T> {
+    pub fn new<F: FnOnce() -> Vec<nn<T>>>(build: F) -> Checkpoint<T> {
+        // the seed is read before build creates the layers, as Graph::new does
+        let seed = Some(random::seed());
+        Checkpoint {
+            network: build(),
+            seed,
+            normalize: None
+        }
+    }
+
+    pub fn infer(&self, input: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        // input: raw samples, as they were before the normalization of the training
+        match &self.normalize {
+            Some(normalize) => infer(&self.network, &normalize.normalize(input)),
+            None => infer(&self.network, input),
+        }
+    }
+
+    pub fn predict(&self, test_inputs: &Vec<Vec<Array2<T>>>, target: &Array2<T>) -> T {
+        let output = self.infer(test_inputs);
+        evaluate(&output[0][0], target) / T::from_usize(test_inputs.len())
     }
 }
 
-pub fn save(network: Vec<nn>, path: &str) {
+pub fn normalized<T: Float>(inputs: Vec<Vec<Array2<T>>>, normalize: Option<&Normalize>) -> Vec<Vec<Array2<T>>> {
+    // the inputs as the network is trained on them, normalize is the one of the Checkpoint
+    match normalize {
+        Some(normalize) => normalize.normalize(&inputs),
+        None => inputs,
+    }
+}
+
+pub fn save<T: Float>(checkpoint: Checkpoint<T>, path: &str) {
     let mut file = OpenOptions::new()
         .write(true)
-        .append(true)
+        .truncate(true)
         .create(true)
         .open(Path::new(path))
         .unwrap();
 
-    for (i, layer) in network.into_iter().enumerate() {
-        println!("saving [{:?}] layer...", i);
-        file.write_all(&layer.to_string().as_bytes()).expect("failed to save current layer");
-    }
+    println!("saving {:?} layers...", checkpoint.network.len());
+    file.write_all(CheckpointJson::new(checkpoint).to_string().as_bytes()).expect("failed to save the network");
 }
 
-pub fn train(
-    network: &mut Vec<nn>, 
+pub fn load<T: Float>(path: &str) -> Result<Checkpoint<T>, LoadError> {
+    let text = fs::read_to_string(Path::new(path))?;
+    let checkpoint: CheckpointJson<T> = serde_json::from_str(&text)?;
+    checkpoint.to_layer()
+}
+
+pub fn train<T: Float>(
+    network: &mut Vec<nn<T>>, 
     epochs: usize, 
-    inputs: Vec<Vec<Array2<f32>>>,
-    test_inputs: Vec<Vec<Array2<f32>>>,
-    train_target: Array2<f32>,
-    test_target: Array2<f32>
+    inputs: Vec<Vec<Array2<T>>>,
+    test_inputs: Vec<Vec<Array2<T>>>,
+    train_target: Array2<T>,
+    test_target: Array2<T>,
+    normalize: Option<&Normalize>
 ) {
     //target [sample, 1 * 10]
     //inputs [sample, 1, 28 * 28]
-    let samples = inputs.len() as f32;
+    // the activations are kept in a workspace reused by every epoch
+    let inputs = normalized(inputs, normalize);
+    let samples = T::from_usize(inputs.len());
+    let mut workspace = Workspace::new(network, &inputs[..inputs.len().min(1)]);
 
     for epoch in 0..epochs {
         println!("******************************************");
         println!("Starting #{:?}# Epoch...", epoch);
 
-        let mut outputs = forward(network, &inputs);
-        let final_output = outputs.pop().unwrap();
+        let final_output = forward_with(network, &inputs, &mut workspace);
 
         let loss = compute_loss(&final_output[0][0], &train_target);
-        let deltas = vec![vec![final_output[0][0].clone() - &train_target]];
+        let deltas = vec![vec![&final_output[0][0] - &train_target]];
         let accuracy = evaluate(&final_output[0][0], &train_target) / samples;
 
         // let test_accuracy = predict(network, &test_inputs, &test_target);
 
         println!("Starting Backward...");
-        backward(network, outputs, deltas);
+        gradients_with(network, &mut workspace, deltas);
+        apply(network, &workspace.gradients);
         println!("Epoch#{:?}# loss: {:?}, Train-Acc: {:?}", epoch, loss, accuracy);
 
     }
 }
 
-pub fn predict(network: &mut Vec<nn>, test_inputs: &Vec<Vec<Array2<f32>>>, target: &Array2<f32>) -> f32 {
+pub fn train_with_loader<T: Float, D: Dataset + 'static>(
+    network: &mut Vec<nn<T>>,
+    epochs: usize,
+    loader: &DataLoader<D>,
+    normalize: Option<&Normalize>
+) -> Result<(), DatasetError> {
+    // one update per batch of the loader, the samples are only loaded when their batch comes
+    // normalize is the one saved with the network (Checkpoint::normalize), applied after the transforms of the loader
+    // stops at the first sample that cannot be loaded
+    // the workspace is built on the first batch and reused by the next ones, a smaller last batch only resizes it
+    let samples = T::from_usize(loader.samples());
+    let mut workspace: Option<Workspace<T>> = None;
+
+    for epoch in 0..epochs {
+        println!("******************************************");
+        println!("Starting #{:?}# Epoch...", epoch);
+
+        let mut correct = T::zero();
+        let mut loss = T::zero();
+
+        for batch in loader.iter()? {
+            let (inputs, labels) = batch?;
+            let inputs = inputs.into_iter().map(|channels| {
+                channels.into_iter().map(|arr| arr.mapv(T::from_f32)).collect::<Vec<Array2<T>>>()
+            }).collect::<Vec<Vec<Array2<T>>>>();
+            let inputs = normalized(inputs, normalize);
+            let labels = labels.mapv(T::from_f32);
+
+            let workspace = workspace.get_or_insert_with(|| Workspace::new(network, &inputs));
+            let final_output = forward_with(network, &inputs, workspace);
+
+            // compute_loss is the mean of the batch, weighted by its size so the epoch loss is the mean of every sample
+            loss += compute_loss(&final_output[0][0], &labels) * T::from_usize(labels.nrows());
+            correct += evaluate(&final_output[0][0], &labels);
+            let deltas = vec![vec![&final_output[0][0] - &labels]];
+            gradients_with(network, workspace, deltas);
+            apply(network, &workspace.gradients);
+        }
+
+        let train_accuracy = correct / samples;
+        println!("Epoch#{:?}# Train-Acc: {:?} loss: {:?}", epoch, train_accuracy, loss / samples);
+    }
+    Ok(())
+}
+
+pub fn predict<T: Float>(network: &Vec<nn<T>>, test_inputs: &Vec<Vec<Array2<T>>>, target: &Array2<T>) -> T {
 
     let samples = test_inputs.len();
 
-    let output = network.iter().fold(test_inputs.clone(), |out, layer| {
-        layer.forward(&out)
-    }); // [1, 1, sample * 10]
+    let output = infer(network, test_inputs); // [1, 1, sample * 10]
 
-    evaluate(&output[0][0], &target) / samples as f32
+    evaluate(&output[0][0], &target) / T::from_usize(samples)
 
 }
 
 
-pub fn train_one_by_one(
-    network: &mut Vec<nn>, 
+pub fn train_one_by_one<T: Float>(
+    network: &mut Vec<nn<T>>, 
     epochs: usize, 
-    inputs: Vec<Vec<Array2<f32>>>,
-    target: Array2<f32>
+    inputs: Vec<Vec<Array2<T>>>,
+    target: Array2<T>,
+    normalize: Option<&Normalize>
 ) {
-    let samples = inputs.len() as f32;
+    // every sample reuses the activations and scratch matrices of the same workspace
+    let inputs = normalized(inputs, normalize);
+    let samples = T::from_usize(inputs.len());
+    let mut workspace = Workspace::new(network, &inputs[..inputs.len().min(1)]);
 
     for epoch in 0..epochs {
         println!("******************************************");
         println!("Starting #{:?}# Epoch...", epoch);
 
-        let mut correct = 0.;
-        let mut loss = 0.;
+        let mut correct = T::zero();
+        let mut loss = T::zero();
 
         timing!({
             for (i, input) in inputs.iter().enumerate() {
-                let mut outputs = forward(network, &vec![input.clone()]);
-                let final_output = outputs.pop().unwrap();
-                
+                let final_output = forward_with(network, std::slice::from_ref(input), &mut workspace);
+
                 let label = target.row(i).to_owned().into_shape((1, 10)).unwrap();
                 loss += compute_loss(&final_output[0][0], &label);
-                let deltas = vec![vec![final_output[0][0].clone() - &label]];
+                let deltas = vec![vec![&final_output[0][0] - &label]];
     
                 correct += evaluate(&final_output[0][0], &label);
-                backward(network, outputs, deltas);
+                gradients_with(network, &mut workspace, deltas);
+                apply(network, &workspace.gradients);
             }
         });
         
@@ -0,0 +1,174 @@
+use crate::propagation::{Propagation, Gradients};
+use crate::utils;
+use utils::{_group_norm, _group_norm_backward};
+
+use ndarray::{Array, Array2, Axis};
+use crate::float::Float;
+use std::sync::RwLock;
+use rayon::prelude::*;
+
+pub const EPSILON: f32 = 1e-5;
+
+// (derivate_gamma, derivate_beta) of one sample
+type Derivates<T> = (Array2<T>, Array2<T>);
+
+// dense == 1 means the inputs come from a FullLayer: [1, 1, sample, neurons]
+// otherwise the inputs come from a conv/pool layer: [sample, channel, width, width]
+// for dense inputs every neuron is treated as a channel with a single pixel
+pub struct GroupNorm<T = f32> {
+    pub groups: usize,
+    pub channels: usize,
+    pub dense: usize,
+    pub alpha: T,
+    pub gamma: RwLock<Array2<T>>,
+    pub beta: RwLock<Array2<T>>
+}
+
+impl<T: Float> Propagation<T> for GroupNorm<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        let samples = self.to_samples(inputs);
+        let gamma = self.gamma.read().unwrap();
+        let beta = self.beta.read().unwrap();
+
+        let outputs = samples.par_iter().map(|sample| {
+            let (normalized, _) = _group_norm(sample, self.groups, T::from_f32(EPSILON));
+            &normalized * &*gamma + &*beta
+        }).collect::<Vec<Array2<T>>>();
+
+        self.restore_layout(outputs, inputs)
+    }
+
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        // next_deltas has the same layout as inputs
+        // the normalized inputs and inv_std of every group are computed again from inputs
+        let samples = self.to_samples(inputs);
+        let deltas = self.to_samples(&next_deltas);
+        let sample = deltas.len();
+        let gamma = self.gamma.read().unwrap();
+
+        let (outputs, derivates): (Vec<Array2<T>>, Vec<Derivates<T>>) = deltas.par_iter().zip(samples.par_iter()).map(|(delta, input)| {
+            let (normalized, inv_stds) = _group_norm(input, self.groups, T::from_f32(EPSILON));
+            let derivate_gamma = (delta * &normalized).sum_axis(Axis(1)).insert_axis(Axis(1));
+            let derivate_beta = delta.sum_axis(Axis(1)).insert_axis(Axis(1));
+
+            (_group_norm_backward(&(delta * &*gamma), &normalized, &inv_stds), (derivate_gamma, derivate_beta))
+        }).unzip();
+
+        let (derivate_gamma, derivate_beta) = derivates.into_iter().fold(
+            (Array::zeros((self.channels, 1)), Array::zeros((self.channels, 1))),
+            |(acc_gamma, acc_beta): (Array2<T>, Array2<T>), (g, b)| (acc_gamma + g, acc_beta + b)
+        );
+        (self.restore_layout(outputs, inputs), Gradients::new(vec![derivate_gamma, derivate_beta], sample))
+    }
+
+    fn apply(&self, gradients: &Gradients<T>) {
+        self.update(&gradients.derivates[0], &gradients.derivates[1], gradients.samples);
+    }
+
+    fn parameters(&self) -> Vec<Array2<T>> {
+        vec![self.gamma.read().unwrap().clone(), self.beta.read().unwrap().clone()]
+    }
+
+    fn set_parameters(&self, parameters: &[Array2<T>]) {
+        *self.gamma.write().unwrap() = parameters[0].clone();
+        *self.beta.write().unwrap() = parameters[1].clone();
+    }
+}
+
+impl<T: Float> GroupNorm<T> {
+    pub fn new(groups: usize, channels: usize, alpha: T, dense: usize) -> GroupNorm<T> {
+        assert!(groups > 0, "GroupNorm needs at least 1 group");
+        assert!(channels.is_multiple_of(groups), "channels must be divisible by groups");
+
+        GroupNorm {
+            groups,
+            channels,
+            dense,
+            alpha,
+            gamma: RwLock::new(Array::ones((channels, 1))),
+            beta: RwLock::new(Array::zeros((channels, 1)))
+        }
+    }
+}
+
+impl<T: Float> GroupNorm<T> {
+    fn to_samples(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Array2<T>> {
+        // output [sample, channel, pixels]
+        if self.dense == 1 {
+            inputs[0][0].axis_iter(Axis(0)).map(|row| {
+                row.to_owned().insert_axis(Axis(1))
+            }).collect::<Vec<Array2<T>>>()
+        } else {
+            inputs.par_iter().map(|input| {
+                let pixels = input[0].len();
+                let data = input.iter().flat_map(|arr| arr.iter().copied()).collect::<Vec<T>>();
+                Array2::from_shape_vec((self.channels, pixels), data).unwrap()
+            }).collect::<Vec<Array2<T>>>()
+        }
+    }
+
+    fn restore_layout(&self, samples: Vec<Array2<T>>, like: &[Vec<Array2<T>>]) -> Vec<Vec<Array2<T>>> {
+        // restore [sample, channel, pixels] to the layout of like
+        if self.dense == 1 {
+            let rows = samples.len();
+            let data = samples.into_iter().flat_map(|sample| sample.into_raw_vec()).collect::<Vec<T>>();
+            vec![vec![Array2::from_shape_vec((rows, self.channels), data).unwrap()]]
+        } else {
+            samples.into_iter().zip(like.iter()).map(|(sample, input)| {
+                sample.axis_iter(Axis(0)).zip(input.iter()).map(|(row, arr)| {
+                    row.to_owned().into_shape(arr.raw_dim()).unwrap()
+                }).collect::<Vec<Array2<T>>>()
+            }).collect::<Vec<Vec<Array2<T>>>>()
+        }
+    }
+
+    fn update(&self, derivate_gamma: &Array2<T>, derivate_beta: &Array2<T>, sample: usize) {
+        let cloned_gamma = self.gamma.read().unwrap().clone();
+        let cloned_beta = self.beta.read().unwrap().clone();
+
+        *self.gamma.write().unwrap() = cloned_gamma - derivate_gamma * self.alpha / T::from_usize(sample);
+        *self.beta.write().unwrap() = cloned_beta - derivate_beta * self.alpha / T::from_usize(sample);
+    }
+}
+
+// LayerNorm normalizes every sample over all of its channels and pixels
+// it is a GroupNorm with a single group, gamma and beta are per channel (per neuron for dense inputs)
+pub struct LayerNorm<T = f32> {
+    pub norm: GroupNorm<T>
+}
+
+impl<T: Float> Propagation<T> for LayerNorm<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        self.norm.forward(inputs)
+    }
+
+    fn infer(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        self.norm.infer(inputs)
+    }
+
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        self.norm.gradients(inputs, next_deltas)
+    }
+
+    fn apply(&self, gradients: &Gradients<T>) {
+        self.norm.apply(gradients)
+    }
+
+    fn parameters(&self) -> Vec<Array2<T>> {
+        self.norm.parameters()
+    }
+
+    fn set_parameters(&self, parameters: &[Array2<T>]) {
+        self.norm.set_parameters(parameters)
+    }
+}
+
+impl<T: Float> LayerNorm<T> {
+    pub fn new(channels: usize, alpha: T, dense: usize) -> LayerNorm<T> {
+        LayerNorm {
+            norm: GroupNorm::new(1, channels, alpha, dense)
+        }
+    }
+}
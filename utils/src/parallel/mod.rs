@@ -0,0 +1,108 @@
+use rayon::{ThreadPoolBuildError, ThreadPoolBuilder};
+use rayon::prelude::*;
+use ndarray::{s, Array2};
+use std::cmp::min;
+
+use crate::dataset::Normalize;
+use crate::network::{nn, forward_with, gradients_with, apply, normalized};
+use crate::workspace::Workspace;
+use crate::float::Float;
+use crate::utils::utils::{compute_loss, evaluate};
+
+// the per-sample loops of the layers run on the global rayon thread pool
+// by default it has one thread per core (or RAYON_NUM_THREADS when it is set)
+// set_threads must be called before the first forward/backward, the pool can only be configured once,
+// it returns an error when the pool is already initialized
+pub fn set_threads(threads: usize) -> Result<(), ThreadPoolBuildError> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+}
+
+pub fn threads() -> usize {
+    rayon::current_num_threads()
+}
+
+// data parallel training
+// every mini-batch is split into shards of shard_size samples, the shards are run on `workers` threads
+// each shard has its own workspace (activations, im2col and gradient buffers) reused by every batch,
+// the layers are only read
+// the gradients are reduced in shard order and applied once per mini-batch,
+// so the result does not depend on the number of workers (workers = 1 is the single-threaded training)
+#[allow(clippy::too_many_arguments)]
+pub fn train_data_parallel<T: Float>(
+    network: &mut Vec<nn<T>>,
+    epochs: usize,
+    batch_size: usize,
+    shard_size: usize,
+    workers: usize,
+    inputs: Vec<Vec<Array2<T>>>,
+    target: Array2<T>,
+    normalize: Option<&Normalize>
+) {
+    //target [sample, classes]
+    //inputs [sample, channel, width, width]
+    // normalize: the one of the Checkpoint, see network::normalized
+    assert!(batch_size > 0 && shard_size > 0, "batch_size and shard_size must be at least 1");
+    let inputs = normalized(inputs, normalize);
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .expect("failed to build the worker threads");
+    let samples = inputs.len();
+    let shard_size = min(shard_size, batch_size);
+
+    let example = &inputs[..min(shard_size, samples)];
+    let mut workspaces = (0..batch_size.div_ceil(shard_size)).map(|_| {
+        Workspace::new(network, example)
+    }).collect::<Vec<Workspace<T>>>();
+
+    for epoch in 0..epochs {
+        println!("******************************************");
+        println!("Starting #{:?}# Epoch...", epoch);
+
+        let mut correct = T::zero();
+        let mut loss = T::zero();
+
+        for start in (0..samples).step_by(batch_size) {
+            let end = min(start + batch_size, samples);
+            let shards = (start..end).step_by(shard_size)
+                .map(|s| (s, min(s + shard_size, end)))
+                .collect::<Vec<(usize, usize)>>();
+            let used = &mut workspaces[..shards.len()];
+
+            let replica: &Vec<nn<T>> = network;
+            let results = pool.install(|| {
+                used.par_iter_mut().zip(shards.par_iter()).map(|(workspace, &(s, e))| {
+                    let final_output = forward_with(replica, &inputs[s..e], workspace);
+
+                    let label = target.slice(s![s..e, ..]).to_owned();
+                    let shard_loss = compute_loss(&final_output[0][0], &label) * T::from_usize(e - s);
+                    let shard_correct = evaluate(&final_output[0][0], &label);
+                    let deltas = vec![vec![&final_output[0][0] - &label]];
+
+                    gradients_with(replica, workspace, deltas);
+                    (shard_loss, shard_correct)
+                }).collect::<Vec<(T, T)>>()
+            });
+
+            for (shard_loss, shard_correct) in results {
+                loss += shard_loss;
+                correct += shard_correct;
+            }
+
+            // all-reduce: add the gradients of every shard into the first one, always in the same order
+            let (reduced, others) = used.split_at_mut(1);
+            for other in others.iter() {
+                for (total, gradient) in reduced[0].gradients.iter_mut().zip(other.gradients.iter()) {
+                    total.accumulate(gradient);
+                }
+            }
+
+            apply(network, &reduced[0].gradients);
+        }
+
+        let train_accuracy = correct / T::from_usize(samples);
+        println!("Epoch#{:?}# Train-Acc: {:?} loss: {:?}", epoch, train_accuracy, loss);
+    }
+}
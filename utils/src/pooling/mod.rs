@@ -1,8 +1,9 @@
 use ndarray::Array2;
-use crate::propagation::Propagation;
+use crate::float::Float;
+use crate::propagation::{Propagation, Gradients};
 use crate::utils;
-use utils::{_max_pool, _upsample};
-use std::cell::RefCell;
+use utils::{_max_pool, _max_pool_values, _upsample};
+use rayon::prelude::*;
 
 pub struct Pool {
     pub width: usize,
@@ -10,34 +11,40 @@ pub struct Pool {
     pub padding: usize,
     pub boundary: usize,
     pub out_channel: usize,
-    pub input_width: usize,
-    pub positions: RefCell<Vec<Vec<Vec<usize>>>>
+    pub input_width: usize
 }
 
-impl Propagation for Pool {
-    fn forward(&self, inputs:&Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
+impl<T: Float> Propagation<T> for Pool {
+    fn forward(&self, inputs:&Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
 
         // at pooling layer, out_channel==in_channel
         // inputs [sample, out_channel, input_width, input_width]
-        // positions [sample, out_channel, index]    (index < input_width * input_width)
-        let mut positions: Vec<Vec<Vec<usize>>> = vec![]; 
-
-        let outputs = inputs.iter().map(|input| {
-            let (output, pos) = self.single_max_pool(input);
-            positions.push(pos);
-            output
-        }).collect::<Vec<Vec<Array2<f32>>>>();
+        // nothing is kept for backward, gradients finds the max positions again
+        self.infer(inputs)
+    }
 
-        *self.positions.borrow_mut() = positions;
-        outputs
+    fn infer(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        // only the max values, without their positions
+        inputs.par_iter().map(|input| {
+            input.iter().map(|val| {
+                _max_pool_values(val, self.width, self.stride, self.padding, self.input_width)
+            }).collect::<Vec<Array2<T>>>()
+        }).collect::<Vec<Vec<Array2<T>>>>()
     }
 
-    fn backward(&self, _: Vec<Vec<Array2<f32>>>, next_deltas: Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>> {
+    fn gradients(&self, inputs: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
         // next_deltas [samples, out_channel, output_width, output_width]
+        // the max positions are found again from inputs instead of being cached by forward
+        // positions [out_channel, index]    (index < input_width * input_width)
+        let samples = next_deltas.len();
+
+        let deltas = next_deltas.into_par_iter().zip(inputs.par_iter()).map(|(delta, input)| {
+            let pos = self.max_positions(input);
+            self.single_upsample(delta, &pos)
+        }).collect::<Vec<Vec<Array2<T>>>>();
 
-        next_deltas.into_iter().zip(self.positions.borrow().iter()).map(|(delta, pos)| {
-            self.single_upsample(delta, pos)
-        }).collect::<Vec<Vec<Array2<f32>>>>()
+        (deltas, Gradients::empty(samples))
     }
 }
 
@@ -56,8 +63,7 @@ impl Pool {
             padding,
             out_channel,
             input_width,
-            boundary,
-            positions: RefCell::new(vec![])
+            boundary
         }
     }
 
@@ -65,22 +71,15 @@ impl Pool {
 
 impl Pool {
 
-    fn single_max_pool(&self, input: &Vec<Array2<f32>>) -> (Vec<Array2<f32>>, Vec<Vec<usize>>) {
+    fn max_positions<T: Float>(&self, input: &Vec<Array2<T>>) -> Vec<Vec<usize>> {
         // input [out_channel, input_width, input_width]
-        // outputs [out_channel, output_width, output_width]
         // max_positions [out_channel, index]
-        let mut outputs: Vec<Array2<f32>> = vec![];
-        let mut max_positions: Vec<Vec<usize>> = vec![];
-
-        for val in input.iter() {
-            let (output, position) = _max_pool(val, self.width, self.stride, self.padding, self.input_width);
-            outputs.push(output);
-            max_positions.push(position);
-        }
-        (outputs, max_positions)
+        input.iter().map(|val| {
+            _max_pool(val, self.width, self.stride, self.padding, self.input_width).1
+        }).collect::<Vec<Vec<usize>>>()
     }
 
-    fn single_upsample(&self, deltas: Vec<Array2<f32>>, pos: &Vec<Vec<usize>>) -> Vec<Array2<f32>> {
+    fn single_upsample<T: Float>(&self, deltas: Vec<Array2<T>>, pos: &Vec<Vec<usize>>) -> Vec<Array2<T>> {
         // deltas [out_channel, output_width, output_width]
         // pos [out_channel, index]
         
@@ -89,6 +88,6 @@ impl Pool {
         
         deltas.into_iter().enumerate().map(|(index, delta)| {
             _upsample(delta, &pos[index], self.input_width)
-        }).collect::<Vec<Array2<f32>>>()
+        }).collect::<Vec<Array2<T>>>()
     }
 }
\ No newline at end of file
@@ -1,11 +1,88 @@
 use ndarray::Array2;
+use crate::float::Float;
+use crate::workspace::Scratch;
+
+// layers are shared by the worker threads, so they must be thread-safe
+// backward must only depend on its inputs (no state cached by forward),
+// so several workers can run forward/backward on the same layer at the same time
+// T is the element type of the layer, see float::Float
+pub trait Propagation<T: Float = f32>: Send + Sync {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>>;
+
+    // forward for serving, nothing is kept for backward
+    // layers can override it to skip the work that is only needed by training
+    fn infer(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        self.forward(inputs)
+    }
+
+    // same as forward, but writes into output and keeps its temporary matrices in scratch
+    // layers can override it to reuse the arrays of output and scratch instead of allocating new ones
+    fn forward_into(&self, inputs: &Vec<Vec<Array2<T>>>, output: &mut Vec<Vec<Array2<T>>>, _scratch: &mut Scratch<T>) {
+        *output = self.forward(inputs);
+    }
+
+    // return (deltas of the inputs, derivatives of the parameters) without updating the layer
+    fn gradients(
+        &self,
+        inputs: &Vec<Vec<Array2<T>>>,
+        deltas: Vec<Vec<Array2<T>>>
+    ) -> (Vec<Vec<Array2<T>>>, Gradients<T>);
+
+    // update the parameters with the derivatives returned by gradients
+    fn apply(&self, _gradients: &Gradients<T>) {}
+
+    // a copy of the parameters, parameters()[i] is the parameter of gradients().1.derivates[i]
+    fn parameters(&self) -> Vec<Array2<T>> {
+        vec![]
+    }
+
+    // overwrite the parameters, in the order of parameters()
+    fn set_parameters(&self, _parameters: &[Array2<T>]) {}
 
-pub trait Propagation {
-    fn forward(&self, inputs: &Vec<Vec<Array2<f32>>>) -> Vec<Vec<Array2<f32>>>;
-    
     fn backward(
-        &self, 
-        inputs: Vec<Vec<Array2<f32>>>,
-        deltas: Vec<Vec<Array2<f32>>>
-    ) -> Vec<Vec<Array2<f32>>>;
-}
\ No newline at end of file
+        &self,
+        inputs: &Vec<Vec<Array2<T>>>,
+        deltas: Vec<Vec<Array2<T>>>
+    ) -> Vec<Vec<Array2<T>>> {
+        let (deltas, gradients) = self.gradients(inputs, deltas);
+        self.apply(&gradients);
+        deltas
+    }
+}
+
+// derivatives of the parameters of a layer, summed over samples
+// layers without parameters have no derivates
+pub struct Gradients<T = f32> {
+    pub derivates: Vec<Array2<T>>,
+    pub samples: usize
+}
+
+impl<T: Float> Gradients<T> {
+    pub fn new(derivates: Vec<Array2<T>>, samples: usize) -> Gradients<T> {
+        Gradients {
+            derivates,
+            samples
+        }
+    }
+
+    pub fn empty(samples: usize) -> Gradients<T> {
+        Gradients::new(vec![], samples)
+    }
+
+    pub fn merge(self, other: Gradients<T>) -> Gradients<T> {
+        // gradients of two disjoint parts of the same batch
+        let derivates = self.derivates.into_iter().zip(other.derivates.iter()).map(|(a, b)| {
+            a + b
+        }).collect::<Vec<Array2<T>>>();
+
+        Gradients::new(derivates, self.samples + other.samples)
+    }
+
+    pub fn accumulate(&mut self, other: &Gradients<T>) {
+        // same as merge, but adds other into self in place
+        for (a, b) in self.derivates.iter_mut().zip(other.derivates.iter()) {
+            *a += b;
+        }
+        self.samples += other.samples;
+    }
+}
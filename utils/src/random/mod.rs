@@ -0,0 +1,49 @@
+use ndarray::{Array, Array2};
+use ndarray_rand::rand_distr::StandardNormal;
+use ndarray_rand::RandomExt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::sync::Mutex;
+
+use crate::float::Float;
+
+// one seeded generator for all the randomness of the library: weight initialization, shuffling, dropout, augmentation
+// set_seed before building the network makes a run reproducible
+// without set_seed the first use draws a seed from the OS, seed() returns it so it can be saved with the model
+// parallel code must not share the generator (the order of the draws would depend on the threads),
+// it takes one fork per task in a fixed order instead
+static GENERATOR: Mutex<Option<(u64, StdRng)>> = Mutex::new(None);
+
+pub fn set_seed(seed: u64) {
+    *GENERATOR.lock().unwrap() = Some((seed, StdRng::seed_from_u64(seed)));
+}
+
+pub fn seed() -> u64 {
+    let mut generator = GENERATOR.lock().unwrap();
+    generator.get_or_insert_with(from_entropy).0
+}
+
+pub fn with_rng<R, F: FnOnce(&mut StdRng) -> R>(f: F) -> R {
+    let mut generator = GENERATOR.lock().unwrap();
+    f(&mut generator.get_or_insert_with(from_entropy).1)
+}
+
+pub fn fork() -> StdRng {
+    // an independent generator seeded from the global one
+    with_rng(|rng| StdRng::seed_from_u64(rng.gen()))
+}
+
+pub fn normal<T: Float>(shape: (usize, usize)) -> Array2<T> {
+    // standard normal samples, drawn in f32 so f32 and f64 models with the same seed start from the same weights
+    with_rng(|rng| Array::<f32, _>::random_using(shape, StandardNormal, rng)).mapv(T::from_f32)
+}
+
+pub fn shuffle<X>(items: &mut [X]) {
+    with_rng(|rng| items.shuffle(rng))
+}
+
+fn from_entropy() -> (u64, StdRng) {
+    let seed = rand::thread_rng().gen();
+    (seed, StdRng::seed_from_u64(seed))
+}
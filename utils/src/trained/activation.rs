@@ -3,7 +3,7 @@ use serde_json;
 use std::fmt::Debug;
 
 use crate::activation::Activation;
-use crate::trained::Convert;
+use crate::trained::{Convert, LoadError};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ActivationJson {
@@ -17,11 +17,11 @@ impl Convert<Activation, ActivationJson> for ActivationJson {
         }
     }
 
-    fn to_layer(self) -> Activation {
+    fn to_layer(self) -> Result<Activation, LoadError> {
         
-        Activation {
+        Ok(Activation {
             end: self.end
-        }
+        })
     }
 }
 
@@ -2,16 +2,27 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 use std::fmt::Debug;
 
-use ndarray::{Array,Array2};
-use std::cell::RefCell;
+use ndarray::Array2;
+use std::sync::RwLock;
 
-use crate::convolution::{Conv2D, Conv3D};
-use crate::utils::utils::cal_shape;
-use crate::trained::Convert;
+use crate::convolution::{Conv3D, ConvTranspose2D, SeparableConv};
+use crate::float::Float;
+use crate::trained::{Convert, LoadError, default_dtype, check_dtype, to_matrix};
 use std::string::ToString;
 
+// models saved before dilation/groups were supported use normal convolutions
+fn default_dilation() -> usize {
+    1
+}
+
+fn default_groups() -> usize {
+    1
+}
+
 #[derive(Deserialize, Serialize, Debug)]
-pub struct Conv3DJson {
+pub struct Conv3DJson<T = f32> {
+    #[serde(default = "default_dtype")]
+    pub dtype: String,
     pub in_channel: usize,
     pub out_channel: usize,
     pub stride: usize,
@@ -19,19 +30,69 @@ pub struct Conv3DJson {
     pub prev_width: usize,
     pub output_width: usize,
     pub filter_width: usize,
-    pub alpha: f32,
+    #[serde(default = "default_dilation")]
+    pub dilation: usize,
+    #[serde(default = "default_groups")]
+    pub groups: usize,
+    pub alpha: T,
     pub boundary: usize,
-    pub conv2d: Vec<Vec<Conv2DJson>>
+    #[serde(flatten)]
+    pub parameters: Conv3DParameters<T>
 }
 
-impl Convert<Conv3D, Conv3DJson> for Conv3DJson {
-    fn new(conv: Conv3D) -> Conv3DJson {
-        let conv2d_json: Vec<Vec<Conv2DJson>> = conv.conv2d.into_inner().into_iter()
-            .map(|convs| {
-                convs.into_iter().map(|conv| Conv2DJson::new(conv)).collect::<Vec<Conv2DJson>>()
-            }).collect();
+// the first Conv3D was a grid of Conv2D [out_channel][in_channel], each with its own filter and a bias per output row,
+// its layers are loaded by folding the filters into the rows of weights,
+// a layer whose biases are not the same for every row is a LoadError instead of a model that predicts differently
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub enum Conv3DParameters<T = f32> {
+    // weights [out_channel, in_channel / groups * filter_width * filter_width], bias [out_channel]
+    Matrices { weights: Vec<T>, bias: Vec<T> },
+    Legacy { conv2d: Vec<Vec<Conv2DJson<T>>> }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Conv2DJson<T = f32> {
+    pub prev: usize,
+    pub filter_width: usize,
+    pub filter: Vec<T>,
+    // one bias per output row
+    pub bias: Vec<T>,
+    pub stride: usize,
+    pub padding: usize,
+    pub alpha: T
+}
+
+impl<T: Float> Conv3DParameters<T> {
+    fn into_matrices(self) -> Result<(Vec<T>, Vec<T>), LoadError> {
+        match self {
+            Conv3DParameters::Matrices { weights, bias } => Ok((weights, bias)),
+            Conv3DParameters::Legacy { conv2d } => {
+                // row r of an output channel got the bias of row r of every Conv2D, summed over the input channels,
+                // a Conv3D has one bias per output channel so the sums must be the same for every row
+                let weights = conv2d.iter().flat_map(|convs| convs.iter().flat_map(|conv| conv.filter.iter().copied())).collect();
+                let bias = conv2d.iter().enumerate().map(|(out, convs)| {
+                    let rows = convs.iter().map(|conv| conv.bias.len()).max().unwrap_or(0);
+                    let sums = (0..rows).map(|r| {
+                        convs.iter().fold(T::zero(), |acc, conv| acc + conv.bias.get(r).copied().unwrap_or_else(T::zero))
+                    }).collect::<Vec<T>>();
+                    match sums.first() {
+                        Some(&first) if sums.iter().any(|&sum| sum != first) => Err(LoadError::Legacy(format!(
+                            "the biases of output channel {} differ between rows, a Conv3D has one bias per channel", out
+                        ))),
+                        first => Ok(first.copied().unwrap_or_else(T::zero)),
+                    }
+                }).collect::<Result<Vec<T>, LoadError>>()?;
+                Ok((weights, bias))
+            }
+        }
+    }
+}
 
+impl<T: Float> Convert<Conv3D<T>, Conv3DJson<T>> for Conv3DJson<T> {
+    fn new(conv: Conv3D<T>) -> Conv3DJson<T> {
         Conv3DJson {
+            dtype: T::DTYPE.to_string(),
             in_channel: conv.in_channel,
             out_channel: conv.out_channel,
             stride: conv.stride,
@@ -39,21 +100,31 @@ impl Convert<Conv3D, Conv3DJson> for Conv3DJson {
             prev_width: conv.prev_width,
             output_width: conv.output_width,
             filter_width: conv.filter_width,
+            dilation: conv.dilation,
+            groups: conv.groups,
             alpha: conv.alpha,
             boundary: conv.boundary,
-            conv2d: conv2d_json
+            parameters: Conv3DParameters::Matrices {
+                weights: conv.weights.into_inner().unwrap().into_raw_vec(),
+                bias: conv.bias.into_inner().unwrap().into_raw_vec()
+            }
         }
     }
 
-    fn to_layer(self) -> Conv3D {
-
-        let conv2d: Vec<Vec<Conv2D>> = self.conv2d.into_iter().map(
-            |convs_json| {
-                convs_json.into_iter().map(|conv_json| conv_json.to_layer()).collect::<Vec<Conv2D>>()
-            }
-        ).collect();
+    fn to_layer(self) -> Result<Conv3D<T>, LoadError> {
+        check_dtype::<T>(&self.dtype)?;
+        if self.groups == 0 || !self.in_channel.is_multiple_of(self.groups) {
+            return Err(LoadError::Shape(format!("{} input channels can not be split into {} groups", self.in_channel, self.groups)))
+        }
+        let (weights, bias) = self.parameters.into_matrices()?;
+        let weights = to_matrix(
+            "weights",
+            (self.out_channel, self.in_channel / self.groups * self.filter_width * self.filter_width),
+            weights
+        )?;
+        let bias = to_matrix("bias", (self.out_channel, 1), bias)?;
 
-        Conv3D {
+        Ok(Conv3D {
             in_channel: self.in_channel,
             out_channel: self.out_channel,
             stride: self.stride,
@@ -61,14 +132,17 @@ impl Convert<Conv3D, Conv3DJson> for Conv3DJson {
             prev_width: self.prev_width,
             output_width: self.output_width,
             filter_width: self.filter_width,
+            dilation: self.dilation,
+            groups: self.groups,
             alpha: self.alpha,
             boundary: self.boundary,
-            conv2d: RefCell::new(conv2d)
-        }
+            weights: RwLock::new(weights),
+            bias: RwLock::new(bias)
+        })
     }
 }
 
-impl ToString for Conv3DJson {
+impl<T: Float> ToString for Conv3DJson<T> {
     
     fn to_string(&self) -> String {
         serde_json::to_string(&self).unwrap()
@@ -76,59 +150,107 @@ impl ToString for Conv3DJson {
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct Conv2DJson {
-    pub prev: usize,
-    pub filter_width: usize,
-    pub filter: Vec<f32>,
-    pub bias: Vec<f32>,
+pub struct SeparableConvJson<T = f32> {
+    pub depthwise: Conv3DJson<T>,
+    pub pointwise: Conv3DJson<T>
+}
+
+impl<T: Float> Convert<SeparableConv<T>, SeparableConvJson<T>> for SeparableConvJson<T> {
+    fn new(conv: SeparableConv<T>) -> SeparableConvJson<T> {
+        SeparableConvJson {
+            depthwise: Conv3DJson::new(conv.depthwise),
+            pointwise: Conv3DJson::new(conv.pointwise)
+        }
+    }
+
+    fn to_layer(self) -> Result<SeparableConv<T>, LoadError> {
+        Ok(SeparableConv {
+            depthwise: self.depthwise.to_layer()?,
+            pointwise: self.pointwise.to_layer()?
+        })
+    }
+}
+
+impl<T: Float> ToString for SeparableConvJson<T> {
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ConvTranspose2DJson<T = f32> {
+    #[serde(default = "default_dtype")]
+    pub dtype: String,
+    pub in_channel: usize,
+    pub out_channel: usize,
     pub stride: usize,
     pub padding: usize,
-    pub alpha: f32
+    pub output_padding: usize,
+    pub prev_width: usize,
+    pub output_width: usize,
+    pub filter_width: usize,
+    pub alpha: T,
+    pub filters: Vec<Vec<Vec<T>>>,
+    pub bias: Vec<T>
 }
 
-impl Convert<Conv2D, Conv2DJson> for Conv2DJson {
-    fn new(conv: Conv2D) -> Conv2DJson {
-        Conv2DJson {
-            prev: conv.prev,
-            filter_width: conv.filter_width,
-            filter: conv.filter.borrow()
-                .iter()
-                .map(|ele| *ele)
-                .collect::<Vec<f32>>(),
-            bias: conv.bias.borrow()
-                .iter()
-                .map(|ele| *ele)
-                .collect::<Vec<f32>>(),
+impl<T: Float> Convert<ConvTranspose2D<T>, ConvTranspose2DJson<T>> for ConvTranspose2DJson<T> {
+    fn new(conv: ConvTranspose2D<T>) -> ConvTranspose2DJson<T> {
+        let filters: Vec<Vec<Vec<T>>> = conv.filters.into_inner().unwrap().into_iter()
+            .map(|filters| {
+                filters.into_iter().map(|filter| filter.into_raw_vec()).collect::<Vec<Vec<T>>>()
+            }).collect();
+
+        ConvTranspose2DJson {
+            dtype: T::DTYPE.to_string(),
+            in_channel: conv.in_channel,
+            out_channel: conv.out_channel,
             stride: conv.stride,
             padding: conv.padding,
-            alpha: conv.alpha
+            output_padding: conv.output_padding,
+            prev_width: conv.prev_width,
+            output_width: conv.output_width,
+            filter_width: conv.filter_width,
+            alpha: conv.alpha,
+            filters,
+            bias: conv.bias.into_inner().unwrap().into_raw_vec()
         }
     }
 
-    fn to_layer(self) -> Conv2D {
-
-        let filter: Array2<f32> = Array2::from_shape_vec((self.filter_width, self.filter_width), self.filter).unwrap();
-        let bias: Array2<f32> = Array2::from_shape_vec((
-            cal_shape(self.prev, self.filter_width, self.stride, self.padding), 1), 
-            self.bias
-        ).unwrap();
+    fn to_layer(self) -> Result<ConvTranspose2D<T>, LoadError> {
+        check_dtype::<T>(&self.dtype)?;
+        // filters [in_channel][out_channel]
+        if self.filters.len() != self.in_channel || self.filters.iter().any(|filters| filters.len() != self.out_channel) {
+            return Err(LoadError::Shape(format!(
+                "filters are not {} input channels of {} filters", self.in_channel, self.out_channel
+            )))
+        }
+        let width = self.filter_width;
+        let filters = self.filters.into_iter().map(|filters| {
+            filters.into_iter().map(|filter| to_matrix("filter", (width, width), filter)).collect::<Result<Vec<Array2<T>>, LoadError>>()
+        }).collect::<Result<Vec<Vec<Array2<T>>>, LoadError>>()?;
+        let bias = to_matrix("bias", (self.out_channel, 1), self.bias)?;
 
-        Conv2D {
-            prev: self.prev,
-            filter_width: self.filter_width,
-            filter: RefCell::new(filter),
-            bias: RefCell::new(bias),
+        Ok(ConvTranspose2D {
+            in_channel: self.in_channel,
+            out_channel: self.out_channel,
             stride: self.stride,
             padding: self.padding,
+            output_padding: self.output_padding,
+            prev_width: self.prev_width,
+            output_width: self.output_width,
+            filter_width: self.filter_width,
             alpha: self.alpha,
-            transposed_filter: RefCell::new(Array::zeros((self.filter_width, self.filter_width)))
-        }
+            filters: RwLock::new(filters),
+            bias: RwLock::new(bias)
+        })
     }
 }
 
-impl ToString for Conv2DJson {
-    
+impl<T: Float> ToString for ConvTranspose2DJson<T> {
+
     fn to_string(&self) -> String {
         serde_json::to_string(&self).unwrap()
     }
-}
\ No newline at end of file
+}
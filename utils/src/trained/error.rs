@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// everything that can go wrong while loading a saved model
+// Io: the file cannot be opened or read
+// Json: the file is not the json of a model
+// Dtype: the layers were saved with another element type than the one they are loaded as
+// Legacy: a layer of an older format that the current layers cannot reproduce exactly
+// Shape: the parameters of a layer do not have the size of its configuration
+// Graph: the nodes of a graph are not a valid graph (unknown merge or node, duplicated name, cycle)
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Dtype { saved: String, expected: &'static str },
+    Legacy(String),
+    Shape(String),
+    Graph(String)
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Json(error) => write!(f, "invalid model file: {}", error),
+            LoadError::Dtype { saved, expected } => {
+                write!(f, "the layer was saved as {}, can not load it as {}", saved, expected)
+            },
+            LoadError::Legacy(reason) => write!(f, "can not convert the old layer format: {}", reason),
+            LoadError::Shape(reason) => write!(f, "invalid layer: {}", reason),
+            LoadError::Graph(reason) => write!(f, "invalid graph: {}", reason),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            LoadError::Json(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(error: serde_json::Error) -> LoadError {
+        LoadError::Json(error)
+    }
+}
@@ -2,58 +2,62 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 use std::fmt::Debug;
 
-use std::cell::RefCell;
-use ndarray::Array2;
+use std::sync::RwLock;
 
 use crate::full_connected::FullLayer;
-use crate::trained::Convert;
+use crate::float::Float;
+use crate::trained::{Convert, LoadError, default_dtype, check_dtype, to_matrix};
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct FullJson {
+pub struct FullJson<T = f32> {
+    #[serde(default = "default_dtype")]
+    pub dtype: String,
     pub neurons: usize,
     pub prev_neurons: usize,
-    pub alpha: f32,
+    pub alpha: T,
     pub boundary: usize,
-    pub weights: Vec<f32>,
-    pub bias: Vec<f32>
+    pub weights: Vec<T>,
+    pub bias: Vec<T>
 }
 
-impl Convert<FullLayer, FullJson> for FullJson {
-    fn new(full: FullLayer) -> FullJson {
+impl<T: Float> Convert<FullLayer<T>, FullJson<T>> for FullJson<T> {
+    fn new(full: FullLayer<T>) -> FullJson<T> {
 
         FullJson {
+            dtype: T::DTYPE.to_string(),
             neurons: full.neurons,
             prev_neurons: full.prev_neurons,
             alpha: full.alpha,
             boundary: full.boundary,
-            weights: full.weights.into_inner()
+            weights: full.weights.into_inner().unwrap()
                 .into_iter()
                 .map(|ele| *ele)
-                .collect::<Vec<f32>>(),
-            bias: full.bias.into_inner()
+                .collect::<Vec<T>>(),
+            bias: full.bias.into_inner().unwrap()
                 .into_iter()
                 .map(|ele| *ele)
-                .collect::<Vec<f32>>()
+                .collect::<Vec<T>>()
         }
     }
 
-    fn to_layer(self) -> FullLayer {
-        let weights = Array2::from_shape_vec((self.neurons, self.prev_neurons), self.weights).unwrap();
-        let bias = Array2::from_shape_vec((self.neurons, 1), self.bias).unwrap();
+    fn to_layer(self) -> Result<FullLayer<T>, LoadError> {
+        check_dtype::<T>(&self.dtype)?;
+        let weights = to_matrix("weights", (self.neurons, self.prev_neurons), self.weights)?;
+        let bias = to_matrix("bias", (self.neurons, 1), self.bias)?;
 
-        FullLayer {
+        Ok(FullLayer {
             neurons: self.neurons,
             prev_neurons: self.prev_neurons,
             alpha: self.alpha,
             boundary: self.boundary,
-            weights: RefCell::new(weights),
-            bias: RefCell::new(bias)
-        }
+            weights: RwLock::new(weights),
+            bias: RwLock::new(bias)
+        })
 
     }
 }
 
-impl ToString for FullJson {
+impl<T: Float> ToString for FullJson<T> {
     
     fn to_string(&self) -> String {
         serde_json::to_string(&self).unwrap()
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::fmt::Debug;
+
+use crate::dataset::Normalize;
+use crate::graph::{Graph, Node, Op};
+use crate::merge::Merge;
+use crate::float::Float;
+use crate::trained::{Convert, LayerJson, LoadError};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub enum OpJson<T = f32> {
+    Layer(LayerJson<T>),
+    Merge(String)
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NodeJson<T = f32> {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub op: OpJson<T>
+}
+
+impl<T: Float> Convert<Node<T>, NodeJson<T>> for NodeJson<T> {
+    fn new(node: Node<T>) -> NodeJson<T> {
+        let op = match node.op {
+            Op::Layer(layer) => OpJson::Layer(LayerJson::new(layer)),
+            Op::Merge(merge) => OpJson::Merge(merge.name()),
+        };
+
+        NodeJson {
+            name: node.name,
+            inputs: node.inputs,
+            op
+        }
+    }
+
+    fn to_layer(self) -> Result<Node<T>, LoadError> {
+        let op = match self.op {
+            OpJson::Layer(layer) => Op::Layer(layer.to_layer()?),
+            OpJson::Merge(name) => match Merge::from_name(&name) {
+                Some(merge) => Op::Merge(merge),
+                None => return Err(LoadError::Graph(format!("unknown merge layer {}", name))),
+            },
+        };
+
+        Ok(Node {
+            name: self.name,
+            inputs: self.inputs,
+            op
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct GraphJson<T = f32> {
+    pub input: String,
+    pub output: String,
+    pub nodes: Vec<NodeJson<T>>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub normalize: Option<Normalize>
+}
+
+impl<T: Float> Convert<Graph<T>, GraphJson<T>> for GraphJson<T> {
+    fn new(graph: Graph<T>) -> GraphJson<T> {
+        GraphJson {
+            input: graph.input,
+            output: graph.output,
+            nodes: graph.nodes.into_iter().map(NodeJson::new).collect::<Vec<NodeJson<T>>>(),
+            seed: graph.seed,
+            normalize: graph.normalize
+        }
+    }
+
+    fn to_layer(self) -> Result<Graph<T>, LoadError> {
+        let graph = Graph {
+            input: self.input,
+            output: self.output,
+            nodes: self.nodes.into_iter().map(|node| node.to_layer()).collect::<Result<Vec<Node<T>>, LoadError>>()?,
+            seed: self.seed,
+            normalize: self.normalize
+        };
+        graph.check().map_err(LoadError::Graph)?;
+        Ok(graph)
+    }
+}
+
+impl<T: Float> ToString for GraphJson<T> {
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
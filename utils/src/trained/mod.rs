@@ -1,11 +1,88 @@
 use serde_json;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use ndarray::Array2;
+
+use crate::network::nn;
+use crate::float::Float;
 
 pub mod convolution;
 pub mod pooling;
+pub mod upsampling;
 pub mod full_connected;
 pub mod activation;
+pub mod normalization;
+pub mod graph;
+pub mod network;
+pub mod error;
+pub use error::LoadError;
 
 pub trait Convert<T, U> {
     fn new(p: T) -> U;
-    fn to_layer(self) -> T;
+    // fails when the saved layer can not be restored as T (e.g. saved as another dtype, parameters of the wrong size)
+    fn to_layer(self) -> Result<T, LoadError>;
+}
+
+// layers saved before the dtype was recorded are f32
+pub fn default_dtype() -> String {
+    "f32".to_string()
+}
+
+pub fn check_dtype<T: Float>(dtype: &str) -> Result<(), LoadError> {
+    if dtype != T::DTYPE {
+        return Err(LoadError::Dtype { saved: dtype.to_string(), expected: T::DTYPE })
+    }
+    Ok(())
+}
+
+pub fn to_matrix<T>(parameter: &str, shape: (usize, usize), values: Vec<T>) -> Result<Array2<T>, LoadError> {
+    // a parameter saved with the wrong number of values is an error instead of a panic
+    if values.len() != shape.0 * shape.1 {
+        return Err(LoadError::Shape(format!("{} has {} values, expected {}x{}", parameter, values.len(), shape.0, shape.1)))
+    }
+    Ok(Array2::from_shape_vec(shape, values).unwrap())
+}
+
+// tagged json of any layer, used when the layer type is not known in advance (e.g. graph nodes)
+#[derive(Deserialize, Serialize, Debug)]
+pub enum LayerJson<T = f32> {
+    Conv(convolution::Conv3DJson<T>),
+    ConvTranspose(convolution::ConvTranspose2DJson<T>),
+    Separable(convolution::SeparableConvJson<T>),
+    Pool(pooling::PoolJson),
+    Upsample(upsampling::UpsampleJson),
+    Activation(activation::ActivationJson),
+    Full(full_connected::FullJson<T>),
+    LayerNorm(normalization::LayerNormJson<T>),
+    GroupNorm(normalization::GroupNormJson<T>)
+}
+
+impl<T: Float> Convert<nn<T>, LayerJson<T>> for LayerJson<T> {
+    fn new(layer: nn<T>) -> LayerJson<T> {
+        match layer {
+            nn::Conv(conv) => LayerJson::Conv(convolution::Conv3DJson::new(conv)),
+            nn::ConvTranspose(conv) => LayerJson::ConvTranspose(convolution::ConvTranspose2DJson::new(conv)),
+            nn::Separable(conv) => LayerJson::Separable(convolution::SeparableConvJson::new(conv)),
+            nn::Pool(p) => LayerJson::Pool(pooling::PoolJson::new(p)),
+            nn::Upsample(u) => LayerJson::Upsample(upsampling::UpsampleJson::new(u)),
+            nn::Activation(a) => LayerJson::Activation(activation::ActivationJson::new(a)),
+            nn::Full(f) => LayerJson::Full(full_connected::FullJson::new(f)),
+            nn::LayerNorm(n) => LayerJson::LayerNorm(normalization::LayerNormJson::new(n)),
+            nn::GroupNorm(n) => LayerJson::GroupNorm(normalization::GroupNormJson::new(n)),
+        }
+    }
+
+    fn to_layer(self) -> Result<nn<T>, LoadError> {
+        Ok(match self {
+            LayerJson::Conv(conv) => nn::Conv(conv.to_layer()?),
+            LayerJson::ConvTranspose(conv) => nn::ConvTranspose(conv.to_layer()?),
+            LayerJson::Separable(conv) => nn::Separable(conv.to_layer()?),
+            LayerJson::Pool(p) => nn::Pool(p.to_layer()?),
+            LayerJson::Upsample(u) => nn::Upsample(u.to_layer()?),
+            LayerJson::Activation(a) => nn::Activation(a.to_layer()?),
+            LayerJson::Full(f) => nn::Full(f.to_layer()?),
+            LayerJson::LayerNorm(n) => nn::LayerNorm(n.to_layer()?),
+            LayerJson::GroupNorm(n) => nn::GroupNorm(n.to_layer()?),
+        })
+    }
 }
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::fmt::Debug;
+
+use crate::dataset::Normalize;
+use crate::network::{nn, Checkpoint};
+use crate::float::Float;
+use crate::trained::{Convert, LayerJson, LoadError};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CheckpointJson<T = f32> {
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub normalize: Option<Normalize>,
+    pub layers: Vec<LayerJson<T>>
+}
+
+impl<T: Float> Convert<Checkpoint<T>, CheckpointJson<T>> for CheckpointJson<T> {
+    fn new(checkpoint: Checkpoint<T>) -> CheckpointJson<T> {
+        CheckpointJson {
+            seed: checkpoint.seed,
+            normalize: checkpoint.normalize,
+            layers: checkpoint.network.into_iter().map(LayerJson::new).collect::<Vec<LayerJson<T>>>()
+        }
+    }
+
+    fn to_layer(self) -> Result<Checkpoint<T>, LoadError> {
+        Ok(Checkpoint {
+            network: self.layers.into_iter().map(|layer| layer.to_layer()).collect::<Result<Vec<nn<T>>, LoadError>>()?,
+            seed: self.seed,
+            normalize: self.normalize
+        })
+    }
+}
+
+impl<T: Float> ToString for CheckpointJson<T> {
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
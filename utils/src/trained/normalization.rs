@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::fmt::Debug;
+
+use std::sync::RwLock;
+
+use crate::normalization::{GroupNorm, LayerNorm};
+use crate::float::Float;
+use crate::trained::{Convert, LoadError, default_dtype, check_dtype, to_matrix};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct GroupNormJson<T = f32> {
+    #[serde(default = "default_dtype")]
+    pub dtype: String,
+    pub groups: usize,
+    pub channels: usize,
+    pub dense: usize,
+    pub alpha: T,
+    pub gamma: Vec<T>,
+    pub beta: Vec<T>
+}
+
+impl<T: Float> Convert<GroupNorm<T>, GroupNormJson<T>> for GroupNormJson<T> {
+    fn new(norm: GroupNorm<T>) -> GroupNormJson<T> {
+        GroupNormJson {
+            dtype: T::DTYPE.to_string(),
+            groups: norm.groups,
+            channels: norm.channels,
+            dense: norm.dense,
+            alpha: norm.alpha,
+            gamma: norm.gamma.into_inner().unwrap().into_raw_vec(),
+            beta: norm.beta.into_inner().unwrap().into_raw_vec()
+        }
+    }
+
+    fn to_layer(self) -> Result<GroupNorm<T>, LoadError> {
+        check_dtype::<T>(&self.dtype)?;
+        let gamma = to_matrix("gamma", (self.channels, 1), self.gamma)?;
+        let beta = to_matrix("beta", (self.channels, 1), self.beta)?;
+
+        Ok(GroupNorm {
+            groups: self.groups,
+            channels: self.channels,
+            dense: self.dense,
+            alpha: self.alpha,
+            gamma: RwLock::new(gamma),
+            beta: RwLock::new(beta)
+        })
+    }
+}
+
+impl<T: Float> ToString for GroupNormJson<T> {
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LayerNormJson<T = f32> {
+    pub norm: GroupNormJson<T>
+}
+
+impl<T: Float> Convert<LayerNorm<T>, LayerNormJson<T>> for LayerNormJson<T> {
+    fn new(norm: LayerNorm<T>) -> LayerNormJson<T> {
+        LayerNormJson {
+            norm: GroupNormJson::new(norm.norm)
+        }
+    }
+
+    fn to_layer(self) -> Result<LayerNorm<T>, LoadError> {
+        Ok(LayerNorm {
+            norm: self.norm.to_layer()?
+        })
+    }
+}
+
+impl<T: Float> ToString for LayerNormJson<T> {
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
@@ -2,10 +2,8 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 use std::fmt::Debug;
 
-use std::cell::RefCell;
-
 use crate::pooling::Pool;
-use crate::trained::Convert;
+use crate::trained::{Convert, LoadError};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PoolJson {
@@ -29,16 +27,15 @@ impl Convert<Pool, PoolJson> for PoolJson {
         }
     }
 
-    fn to_layer(self) -> Pool {
-        Pool {
+    fn to_layer(self) -> Result<Pool, LoadError> {
+        Ok(Pool {
             width: self.width,
             stride: self.stride,
             padding: self.padding,
             boundary: self.boundary,
             out_channel: self.out_channel,
-            input_width: self.input_width,
-            positions: RefCell::new(vec![])
-        }
+            input_width: self.input_width
+        })
     }
 }
 
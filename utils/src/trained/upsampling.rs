@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::fmt::Debug;
+
+use crate::upsampling::Upsample;
+use crate::float::Float;
+use crate::trained::{Convert, LoadError};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UpsampleJson {
+    pub scale: usize,
+    pub input_width: usize,
+    pub bilinear: usize
+}
+
+impl<T: Float> Convert<Upsample<T>, UpsampleJson> for UpsampleJson {
+    fn new(upsample: Upsample<T>) -> UpsampleJson {
+        UpsampleJson {
+            scale: upsample.scale,
+            input_width: upsample.input_width,
+            bilinear: upsample.bilinear
+        }
+    }
+
+    fn to_layer(self) -> Result<Upsample<T>, LoadError> {
+        Ok(Upsample::new(self.scale, self.input_width, self.bilinear))
+    }
+}
+
+impl ToString for UpsampleJson {
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
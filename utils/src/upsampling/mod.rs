@@ -0,0 +1,49 @@
+use crate::propagation::{Propagation, Gradients};
+use crate::utils;
+use utils::interpolation_matrix;
+
+use ndarray::Array2;
+use crate::float::Float;
+use rayon::prelude::*;
+
+// resizes every channel from [input_width, input_width] to [input_width * scale, input_width * scale]
+// bilinear == 1 means bilinear interpolation, otherwise nearest neighbor
+pub struct Upsample<T = f32> {
+    pub scale: usize,
+    pub input_width: usize,
+    pub bilinear: usize,
+    // [input_width * scale, input_width]
+    pub matrix: Array2<T>
+}
+
+impl<T: Float> Propagation<T> for Upsample<T> {
+    fn forward(&self, inputs: &Vec<Vec<Array2<T>>>) -> Vec<Vec<Array2<T>>> {
+        // inputs [sample, channel, input_width, input_width]
+        // output [sample, channel, input_width * scale, input_width * scale]
+        inputs.par_iter().map(|input| {
+            input.iter().map(|arr| self.matrix.dot(arr).dot(&self.matrix.t())).collect::<Vec<Array2<T>>>()
+        }).collect::<Vec<Vec<Array2<T>>>>()
+    }
+
+    fn gradients(&self, _: &Vec<Vec<Array2<T>>>, next_deltas: Vec<Vec<Array2<T>>>)
+    -> (Vec<Vec<Array2<T>>>, Gradients<T>) {
+        // the interpolation is linear, its derivative is the transposed interpolation
+        let samples = next_deltas.len();
+
+        let deltas = next_deltas.into_par_iter().map(|delta| {
+            delta.into_iter().map(|arr| self.matrix.t().dot(&arr).dot(&self.matrix)).collect::<Vec<Array2<T>>>()
+        }).collect::<Vec<Vec<Array2<T>>>>();
+        (deltas, Gradients::empty(samples))
+    }
+}
+
+impl<T: Float> Upsample<T> {
+    pub fn new(scale: usize, input_width: usize, bilinear: usize) -> Upsample<T> {
+        Upsample {
+            scale,
+            input_width,
+            bilinear,
+            matrix: interpolation_matrix(input_width, scale, bilinear)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::float::Float;
+
+// kernels on contiguous row-major slices
+// the inner loops walk one input row at a time, with step == 1 they are plain slice copies/adds
+// that the compiler can vectorize, the padding is skipped instead of being materialized
+
+fn valid_range(offset: usize, step: usize, padding: usize, input_width: usize, width: usize) -> (usize, usize) {
+    // the k in [lo, hi) for which k * step + offset - padding is inside [0, input_width)
+    if offset >= padding && offset + (width - 1) * step < padding + input_width {
+        return (0, width);
+    }
+    let lo = if offset >= padding { 0 } else { (padding - offset).div_ceil(step) };
+    let hi = if padding + input_width > offset {
+        ((padding + input_width - offset - 1) / step + 1).min(width)
+    } else {
+        0
+    };
+    (lo.min(hi), hi)
+}
+
+pub fn gather<T: Float>(
+    input: &[T],
+    input_width: usize,
+    width: usize,
+    offset: (usize, usize),
+    step: usize,
+    padding: usize,
+    dst: &mut [T]
+) {
+    // input [input_width, input_width] padded by padding zeros on every side
+    // dst [width, width], dst[i, j] = padded[offset.0 + i * step, offset.1 + j * step]
+    // im2col: one window is (offset = window corner, step = dilation, width = filter_width),
+    // one row of the [f * f, out * out] layout is (offset = filter position, step = stride, width = output_width)
+    let (top, bottom) = valid_range(offset.0, step, padding, input_width, width);
+    let (lo, hi) = valid_range(offset.1, step, padding, input_width, width);
+
+    for (i, row) in dst.chunks_mut(width).enumerate() {
+        if i < top || i >= bottom || lo == hi {
+            for d in row.iter_mut() {
+                *d = T::zero();
+            }
+            continue;
+        }
+        let (head, tail) = row.split_at_mut(hi);
+        for d in head[..lo].iter_mut().chain(tail.iter_mut()) {
+            *d = T::zero();
+        }
+
+        let start = (offset.0 + i * step - padding) * input_width + lo * step + offset.1 - padding;
+        if step == 1 {
+            head[lo..].copy_from_slice(&input[start..start + hi - lo]);
+        } else {
+            for (d, &s) in head[lo..].iter_mut().zip(input[start..].iter().step_by(step)) {
+                *d = s;
+            }
+        }
+    }
+}
+
+pub fn scatter_add<T: Float>(
+    src: &[T],
+    width: usize,
+    offset: (usize, usize),
+    step: usize,
+    padding: usize,
+    output: &mut [T],
+    output_width: usize
+) {
+    // reverse of gather: padded[offset.0 + i * step, offset.1 + j * step] += src[i, j]
+    // output [output_width, output_width] is the padded matrix without its padding
+    // the elements are added in row-major order of src
+    let (top, bottom) = valid_range(offset.0, step, padding, output_width, width);
+    let (lo, hi) = valid_range(offset.1, step, padding, output_width, width);
+
+    if lo == hi {
+        return;
+    }
+    for i in top..bottom {
+        let start = (offset.0 + i * step - padding) * output_width + lo * step + offset.1 - padding;
+        let row = &src[i * width + lo..i * width + hi];
+        if step == 1 {
+            for (o, &s) in output[start..start + hi - lo].iter_mut().zip(row.iter()) {
+                *o += s;
+            }
+        } else {
+            for (o, &s) in output[start..].iter_mut().step_by(step).zip(row.iter()) {
+                *o += s;
+            }
+        }
+    }
+}
+
+pub fn max_pool<T: Float>(
+    input: &[T],
+    input_width: usize,
+    width: usize,
+    stride: usize,
+    output: &mut [T],
+    indices: &mut [usize]
+) {
+    // input [input_width, input_width], output/indices [out, out]
+    // indices are positions in input, the first maximum of a window wins
+    // a window with nothing above min_value (e.g. only -inf or NaN) points to its first element
+    let output_width = (input_width - width) / stride + 1;
+
+    for (index, (out, position)) in output.iter_mut().zip(indices.iter_mut()).enumerate() {
+        let (x, y) = (index / output_width * stride, index % output_width * stride);
+        let max = window_max(input, input_width, width, x, y);
+        *out = max.1;
+        *position = max.0;
+    }
+}
+
+pub fn max_pool_values<T: Float>(input: &[T], input_width: usize, width: usize, stride: usize, output: &mut [T]) {
+    // same output as max_pool, without the positions only backward needs
+    let output_width = (input_width - width) / stride + 1;
+
+    for (index, out) in output.iter_mut().enumerate() {
+        let (x, y) = (index / output_width * stride, index % output_width * stride);
+        *out = window_max(input, input_width, width, x, y).1;
+    }
+}
+
+fn window_max<T: Float>(input: &[T], input_width: usize, width: usize, x: usize, y: usize) -> (usize, T) {
+    // (position, value) of the first maximum of the window at (x, y)
+    let mut max = (x * input_width + y, T::min_value());
+
+    for i in 0..width {
+        let start = (x + i) * input_width + y;
+        for (j, &val) in input[start..start + width].iter().enumerate() {
+            let candidate = max.1.max(val);
+            if candidate != max.1 {
+                max = (start + j, candidate);
+            }
+        }
+    }
+    max
+}
+
+pub fn argmax<T: Float>(row: &[T]) -> usize {
+    // index of the first maximum, 0 when no element is positive
+    let mut max = (0, T::zero());
+    for (i, &ele) in row.iter().enumerate() {
+        if ele > max.1 {
+            max = (i, ele);
+        }
+    }
+    max.0
+}
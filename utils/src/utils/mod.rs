@@ -1,65 +1,207 @@
 pub mod utils;
-use ndarray::{Array2, Axis};
+pub mod kernels;
+use ndarray::{s, Array2, ArrayView2, ArrayViewMut2, Axis};
+use crate::float::Float;
+use crate::linalg::dot;
 
-use utils::{flip_matrix, cal_shape, im2col, im2col_filter, _rotate, _restore_max_index};
+use utils::{cal_shape, cal_backward_shape, im2col, im2col_filter, _rotate};
+use kernels::{gather, scatter_add, max_pool, max_pool_values};
 
-pub fn _max_pool(
-    input: &Array2<f32>, 
+pub fn _max_pool<T: Float>(
+    input: &Array2<T>, 
     width: usize, 
     stride: usize, 
     padding: usize, 
     input_width: usize
-) -> (Array2<f32>, Vec<usize>) {
-    let output_width = cal_shape(input_width, width, stride, padding);
-    let mut indices: Vec<usize> = vec![];
+) -> (Array2<T>, Vec<usize>) {
     // return (max_pooled_input, index_max_values)
-    let flipped_matrix: Vec<(usize, usize, f32)> = flip_matrix(input, width, stride)
-    .into_iter()
-    .enumerate()
-    .map(
-        |(index, v)| {
-            let pair = v.into_iter()
-            .enumerate().fold((0, f32::MIN), |(max_pos, acc), (pos, x)| {
-            let max = acc.max(x);
-            if max == acc {
-                (max_pos, acc)
-            } else {
-                (pos, max)
-            }
-        });
-        (index, pair.0, pair.1)
-        }
-    ).collect();
-    let max_values: Vec<f32> = flipped_matrix.into_iter().map(|(index, pos, val)| {
-        indices.push(_restore_max_index(output_width, (index, pos), stride, input.shape()[0], width));
-        val
-    }).collect();
-    (Array2::from_shape_vec((output_width, output_width), max_values).unwrap(), indices)
+    // the windows are taken from the unpadded input
+    let output_width = cal_shape(input_width, width, stride, padding, 1);
+    let input = input.as_standard_layout();
+    let mut output: Array2<T> = Array2::zeros((output_width, output_width));
+    let mut indices: Vec<usize> = vec![0; output_width * output_width];
+
+    max_pool(input.as_slice().unwrap(), input_width, width, stride, output.as_slice_mut().unwrap(), &mut indices);
+    (output, indices)
 }
 
-pub fn _convolution(filter: &Array2<f32>, input: &Array2<f32>, stride: usize, padding: usize) -> Array2<f32> {
+pub fn _max_pool_values<T: Float>(input: &Array2<T>, width: usize, stride: usize, padding: usize, input_width: usize) -> Array2<T> {
+    // the output of _max_pool without the positions, for inference
+    let output_width = cal_shape(input_width, width, stride, padding, 1);
+    let input = input.as_standard_layout();
+    let mut output: Array2<T> = Array2::zeros((output_width, output_width));
+
+    max_pool_values(input.as_slice().unwrap(), input_width, width, stride, output.as_slice_mut().unwrap());
+    output
+}
+
+pub fn _convolution<T: Float>(filter: &Array2<T>, input: &Array2<T>, stride: usize, padding: usize, dilation: usize) -> Array2<T> {
     // return [1, f*f]x[f*f, out*out] = [out, out]
     let filter_width = filter.shape()[0];
     let input_width = input.shape()[0];
-    let output_width = cal_shape(input_width, filter_width, stride, padding);
+    let output_width = cal_shape(input_width, filter_width, stride, padding, dilation);
 
 
-    let im2col_input = im2col(&input, filter_width, stride, padding);
+    let im2col_input = im2col(&input, filter_width, stride, padding, dilation);
     let im2col_filter = im2col_filter(&filter, filter_width);
 
-    im2col_input.dot(&im2col_filter).into_shape((output_width, output_width)).unwrap()
+    dot(&im2col_input, &im2col_filter).into_shape((output_width, output_width)).unwrap()
+}
+
+pub fn _im2col_channels<T: Float>(
+    input: &Vec<Array2<T>>,
+    filter_width: usize,
+    stride: usize,
+    padding: usize,
+    dilation: usize
+) -> Array2<T> {
+    // input [channel, width, width]
+    // return [channel * f * f, out * out], the rows of every channel are contiguous
+    let input_width = input[0].shape()[0];
+    let output_width = cal_shape(input_width, filter_width, stride, padding, dilation);
+    let mut cols = Array2::zeros((input.len() * filter_width * filter_width, output_width * output_width));
+
+    _im2col_channels_into(input, filter_width, stride, padding, dilation, cols.view_mut());
+    cols
+}
+
+pub fn _im2col_channels_into<T: Float>(
+    input: &Vec<Array2<T>>,
+    filter_width: usize,
+    stride: usize,
+    padding: usize,
+    dilation: usize,
+    mut cols: ArrayViewMut2<T>
+) {
+    // same as _im2col_channels, but writes into cols [channel * f * f, out * out]
+    // the padding is read as zeros instead of building a padded copy of every channel
+    // cols may be a column block of a bigger matrix, only its rows have to be contiguous
+    let input_width = input[0].shape()[0];
+    let output_width = cal_shape(input_width, filter_width, stride, padding, dilation);
+    let filter_size = filter_width * filter_width;
+
+    for (channel, data) in input.iter().enumerate() {
+        let data = data.as_standard_layout();
+        let data = data.as_slice().unwrap();
+
+        for position in 0..filter_size {
+            let mut row = cols.row_mut(channel * filter_size + position);
+            let offset = (position / filter_width * dilation, position % filter_width * dilation);
+            gather(data, input_width, output_width, offset, stride, padding, row.as_slice_mut().unwrap());
+        }
+    }
+}
+
+pub fn _col2im_channels<T: Float>(
+    cols: ArrayView2<T>,
+    input_width: usize,
+    filter_width: usize,
+    stride: usize,
+    padding: usize,
+    dilation: usize
+) -> Vec<Array2<T>> {
+    // reverse of _im2col_channels
+    // cols [channel * f * f, out * out] -> [channel, input_width, input_width]
+    // a pixel gets the windows in increasing order, as in col2im: the window index grows when
+    // the filter position decreases, so the rows are added from the last filter position to the first
+    let filter_size = filter_width * filter_width;
+    let output_width = cal_shape(input_width, filter_width, stride, padding, dilation);
+
+    (0..cols.shape()[0] / filter_size).map(|channel| {
+        let mut output: Array2<T> = Array2::zeros((input_width, input_width));
+        let data = output.as_slice_mut().unwrap();
+
+        for position in (0..filter_size).rev() {
+            let row = cols.row(channel * filter_size + position);
+            let row = row.as_standard_layout();
+            let offset = (position / filter_width * dilation, position % filter_width * dilation);
+            scatter_add(row.as_slice().unwrap(), output_width, offset, stride, padding, data, input_width);
+        }
+        output
+    }).collect::<Vec<Array2<T>>>()
+}
+
+pub fn _channels_to_matrix<T: Float>(input: &Vec<Array2<T>>) -> Array2<T> {
+    // [channel, width, width] -> [channel, width * width]
+    let pixels = input[0].len();
+    let data = input.iter().flat_map(|arr| arr.iter().copied()).collect::<Vec<T>>();
+    Array2::from_shape_vec((input.len(), pixels), data).unwrap()
 }
 
+pub fn _matrix_to_channels<T: Float>(matrix: ArrayView2<T>, width: usize) -> Vec<Array2<T>> {
+    // [channel, width * width] -> [channel, width, width]
+    matrix.axis_iter(Axis(0)).map(|row| {
+        row.to_owned().into_shape((width, width)).unwrap()
+    }).collect::<Vec<Array2<T>>>()
+}
+
+pub fn _transposed_convolution<T: Float>(
+    filter: &Array2<T>,
+    input: &Array2<T>,
+    stride: usize,
+    padding: usize,
+    output_padding: usize
+) -> Array2<T> {
+    // every input pixel scatters input[i, j] * filter into the output starting at (i * s, j * s)
+    // the full output is cropped by padding on every side, output_padding is only added at the bottom/right
+    // return [out, out], out = (in - 1) * s - 2p + f + output_padding
+    let filter_width = filter.shape()[0];
+    let full_width = cal_backward_shape(input.shape()[0], filter_width, stride, 0) + output_padding;
+    let mut full: Array2<T> = Array2::zeros((full_width, full_width));
+
+    for ((i, j), &val) in input.indexed_iter() {
+        full.slice_mut(s![i * stride..i * stride + filter_width, j * stride..j * stride + filter_width])
+            .scaled_add(val, filter);
+    }
+    full.slice(s![padding..full_width - padding, padding..full_width - padding]).to_owned()
+}
+
+pub fn _transposed_im2col<T: Float>(
+    delta: &Array2<T>,
+    input_width: usize,
+    filter_width: usize,
+    stride: usize,
+    padding: usize
+) -> Array2<T> {
+    // delta [out, out] of a transposed convolution
+    // restore the cropped padding and collect the window each input pixel scattered into
+    // return [in * in, f * f]
+    let windows = im2col(delta, filter_width, stride, padding, 1);
+    assert_eq!(windows.shape()[0], input_width * input_width);
+    windows
+}
+
+pub fn interpolation_matrix<T: Float>(input_width: usize, scale: usize, bilinear: usize) -> Array2<T> {
+    // [out, in] matrix A so that A * input * A^T resizes input to [out, out], out = in * scale
+    // bilinear == 1 means bilinear interpolation (half-pixel centers), otherwise nearest neighbor
+    let output_width = input_width * scale;
+    let mut matrix: Array2<T> = Array2::zeros((output_width, input_width));
+
+    for row in 0..output_width {
+        if bilinear == 1 {
+            let half = T::from_f32(0.5);
+            let src = ((T::from_usize(row) + half) / T::from_usize(scale) - half).max(T::zero());
+            let low = src.floor().to_usize().unwrap().min(input_width - 1);
+            let high = (low + 1).min(input_width - 1);
+            let weight = src - T::from_usize(low);
+            matrix[[row, low]] += T::one() - weight;
+            matrix[[row, high]] += weight;
+        } else {
+            matrix[[row, row / scale]] = T::one();
+        }
+    }
+    matrix
+}
 
-pub fn rotation(filters: &Vec<Array2<f32>>) -> Vec<Array2<f32>> {
+pub fn rotation<T: Float>(filters: &Vec<Array2<T>>) -> Vec<Array2<T>> {
 
-    filters.iter().map(|filter| _rotate(filter, 1)).collect::<Vec<Array2<f32>>>()
+    filters.iter().map(|filter| _rotate(filter, 1)).collect::<Vec<Array2<T>>>()
 
 }
 
 
-pub fn _upsample(delta: Array2<f32>, positions: &Vec<usize>, input_width: usize) -> Array2<f32> {
-    let mut output: Vec<f32> = (0..input_width * input_width).map(|_| 0.).collect();
+pub fn _upsample<T: Float>(delta: Array2<T>, positions: &Vec<usize>, input_width: usize) -> Array2<T> {
+    let mut output: Vec<T> = (0..input_width * input_width).map(|_| T::zero()).collect();
 
     for (i, &val) in delta.iter().enumerate() {
         output[positions[i]] = val;
@@ -81,54 +223,59 @@ pub fn _upsample(delta: Array2<f32>, positions: &Vec<usize>, input_width: usize)
         };
         println!("index {:?} ele {}", index, ele);
         ele
-    }).collect::<Vec<f32>>(); */
+    }).collect::<Vec<T>>(); */
 
 }
 
-pub fn _restore_with_channel(matrix: Vec<Array2<f32>>,
+pub fn _restore_with_channel<T: Float>(matrix: Vec<Array2<T>>,
     out_channel: usize,
     prev_width: usize,
     filter_width: usize,
     stride: usize,
     padding: usize,
-    boundary: usize) -> Vec<Array2<f32>> {
+    dilation: usize,
+    boundary: usize) -> Vec<Array2<T>> {
     
     if boundary == 1 {
-        let data_width = cal_shape(prev_width, filter_width, stride, padding);
+        let data_width = cal_shape(prev_width, filter_width, stride, padding, dilation);
         let mut data_iter = matrix[0].into_iter();
         
         (0..out_channel).map(|_| {
-            let one_channel_vector: Vec<f32> = (0..data_width * data_width).map(|_| *data_iter.next().unwrap())
+            let one_channel_vector: Vec<T> = (0..data_width * data_width).map(|_| *data_iter.next().unwrap())
             .collect();
             Array2::from_shape_vec((data_width, data_width), one_channel_vector).unwrap()
-        }).collect::<Vec<Array2<f32>>>()
+        }).collect::<Vec<Array2<T>>>()
     } else {
         matrix
     }
 }
 
-pub fn _flatten_withno_channel(
-    inputs: &Vec<Vec<Array2<f32>>>,
+pub fn _flatten_withno_channel<T: Float>(
+    inputs: &Vec<Vec<Array2<T>>>,
     prev_neurons: usize
-) -> Vec<Array2<f32>> {
+) -> Vec<Array2<T>> {
     // inputs [sample, channel, width * width]
     // prev_neurons = channel * width * width
     // at the boundary, flattened pixels == prev_neurons
-    let samples = inputs.len();
-    let vectors = inputs.iter().map(|input| {
-        let input_as_vector = input.iter().map(
-            |v| v.iter().map(|ele| *ele).collect::<Vec<f32>>()
-        ).collect::<Vec<Vec<f32>>>();
-        input_as_vector.into_iter().flatten().collect::<Vec<f32>>()
-    }).collect::<Vec<Vec<f32>>>();
+    let mut flattened = Array2::zeros((inputs.len(), prev_neurons));
+    _flatten_into(inputs, flattened.view_mut());
+    vec![flattened]
+}
 
-    vec![Array2::from_shape_vec((samples, prev_neurons), vectors.into_iter().flatten().collect::<Vec<f32>>()).unwrap()]
+pub fn _flatten_into<T: Float>(inputs: &Vec<Vec<Array2<T>>>, mut output: ArrayViewMut2<T>) {
+    // same as _flatten_withno_channel, but writes into output [sample, prev_neurons]
+    for (input, mut row) in inputs.iter().zip(output.outer_iter_mut()) {
+        let values = input.iter().flat_map(|v| v.iter());
+        for (target, &value) in row.iter_mut().zip(values) {
+            *target = value;
+        }
+    }
 }
     
-pub fn sum_nested_vector(a: Vec<Array2<f32>>, b: Vec<Array2<f32>>) -> Vec<Array2<f32>> {
+pub fn sum_nested_vector<T: Float>(a: Vec<Array2<T>>, b: Vec<Array2<T>>) -> Vec<Array2<T>> {
     a.into_iter().zip(b.iter()).map(|(i, j)| {
         i + j
-    }).collect::<Vec<Array2<f32>>>()
+    }).collect::<Vec<Array2<T>>>()
 }
 
 pub fn one_hot(labels: Array2<f32>, cols: usize) -> Array2<f32> {
@@ -154,3 +301,53 @@ pub fn matrix_to_tensor(matrix: Array2<f32>) -> Vec<Vec<Array2<f32>>> {
         vec![row.to_owned().into_shape((28, 28)).unwrap()]
     }).collect::<Vec<Vec<Array2<f32>>>>()
 }
+
+pub fn _group_norm<T: Float>(
+    input: &Array2<T>,
+    groups: usize,
+    eps: T
+) -> (Array2<T>, Vec<T>) {
+    // input [channel, pixels]
+    // return (normalized_input, inv_std of every group)
+    let group_size = input.shape()[0] / groups;
+    let mut normalized = input.to_owned();
+    let mut inv_stds: Vec<T> = vec![];
+
+    for g in 0..groups {
+        let mut group = normalized.slice_mut(s![g * group_size..(g + 1) * group_size, ..]);
+        let count = T::from_usize(group.len());
+        let mean = group.sum() / count;
+        let var = group.fold(T::zero(), |acc, &x| acc + (x - mean) * (x - mean)) / count;
+        let inv_std = T::one() / (var + eps).sqrt();
+
+        group.mapv_inplace(|x| (x - mean) * inv_std);
+        inv_stds.push(inv_std);
+    }
+    (normalized, inv_stds)
+}
+
+pub fn _group_norm_backward<T: Float>(
+    delta: &Array2<T>,
+    normalized: &Array2<T>,
+    inv_stds: &[T]
+) -> Array2<T> {
+    // delta [channel, pixels], already multiplied by gamma
+    // dx = inv_std / N * (N * dx_hat - sum(dx_hat) - x_hat * sum(dx_hat * x_hat))
+    let groups = inv_stds.len();
+    let group_size = delta.shape()[0] / groups;
+    let mut output = Array2::zeros(delta.raw_dim());
+
+    for (g, inv_std) in inv_stds.iter().enumerate() {
+        let rows = s![g * group_size..(g + 1) * group_size, ..];
+        let d = delta.slice(rows);
+        let x_hat = normalized.slice(rows);
+        let count = T::from_usize(d.len());
+        let sum_d = d.sum();
+        let sum_dx = (&d * &x_hat).sum();
+
+        output.slice_mut(rows).assign(
+            &((&d * count - sum_d - &x_hat * sum_dx) * (*inv_std / count))
+        );
+    }
+    output
+}
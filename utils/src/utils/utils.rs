@@ -1,14 +1,15 @@
-use ndarray::{Array2, Array, Axis};
-use std::f32::consts::E;
+use ndarray::{s, Array2, Axis};
+use crate::float::Float;
+use super::kernels::{gather, scatter_add, argmax};
 
-pub fn im2col_filter(filter: &Array2<f32>, width: usize) -> Array2<f32> {
-    let filter_as_vector = filter.iter().map(|&x| x).collect::<Vec<f32>>();
+pub fn im2col_filter<T: Float>(filter: &Array2<T>, width: usize) -> Array2<T> {
+    let filter_as_vector = filter.iter().map(|&x| x).collect::<Vec<T>>();
     Array2::from_shape_vec((width * width, 1), filter_as_vector).unwrap()
 }
 
-pub fn im2col_input(input: &Array2<f32>, width: usize, filter_width: usize) -> Array2<f32> {
+pub fn im2col_input<T: Float>(input: &Array2<T>, width: usize, filter_width: usize) -> Array2<T> {
     //currently, only support stride=1
-    let mut input_as_vector: Vec<f32> = vec![];
+    let mut input_as_vector: Vec<T> = vec![];
     let windows = input.windows((filter_width, filter_width));
     
     for slide in windows {
@@ -20,73 +21,74 @@ pub fn im2col_input(input: &Array2<f32>, width: usize, filter_width: usize) -> A
 }
 
 
-pub fn padding_input(matrix: &Array2<f32>) -> Array2<f32> {
-    // default pad=1
-    // currently only support pad=1
+pub fn padding_input<T: Float>(matrix: &Array2<T>, padding: usize) -> Array2<T> {
+    // surround the matrix with padding rows/cols of zeros
     let width = matrix.shape()[0];
-    let mut matrix_iter = matrix.into_iter();
-    let mut padded_matrix_as_vec: Vec<f32> = (0..width+2).map(|_| 0.).collect();
+    let mut padded_matrix: Array2<T> = Array2::zeros((width + 2 * padding, width + 2 * padding));
 
-    for _ in 0..width {
-        padded_matrix_as_vec.push(0.);
-        for _ in 0..width {
-            padded_matrix_as_vec.push(*matrix_iter.next().unwrap());
-        }
-        padded_matrix_as_vec.push(0.);
-    }
-
-    padded_matrix_as_vec.append(&mut (0..width+2).map(|_| 0.).collect::<Vec<f32>>());
-    Array2::from_shape_vec((width+2, width+2), padded_matrix_as_vec).unwrap()
+    padded_matrix.slice_mut(s![padding..padding + width, padding..padding + width]).assign(matrix);
+    padded_matrix
 }
 
-pub fn flip_matrix(matrix: &Array2<f32>, width: usize, stride: usize) -> Vec<Vec<f32>> {
+pub fn flip_matrix<T: Float>(matrix: &Array2<T>, width: usize, stride: usize, dilation: usize) -> Vec<Vec<T>> {
     // input must be padded before scan the input array
-    
-    let input_width = matrix.shape()[0];
-    let mut flipped_vector = vec![];
-    let mut x = 0 as usize;
-    let mut y = 0  as usize;
-
+    // dilation inserts (dilation - 1) gaps between the elements of the window
+    let windows = im2col(matrix, width, stride, 0, dilation);
+    windows.outer_iter().map(|window| window.to_vec()).collect::<Vec<Vec<T>>>()
+}
 
-    while x < input_width - width + 1 {
+pub fn im2col<T: Float>(matrix: &Array2<T>, width: usize, stride: usize, padding: usize, dilation: usize) -> Array2<T> {
+    // return [out * out, f * f], one window per row
+    let input_width = matrix.shape()[0];
+    let shape = cal_shape(input_width, width, stride, padding, dilation);
+    let matrix = matrix.as_standard_layout();
+    let input = matrix.as_slice().unwrap();
+    let mut cols: Array2<T> = Array2::zeros((shape * shape, width * width));
+
+    for (index, window) in cols.as_slice_mut().unwrap().chunks_mut(width * width).enumerate() {
+        let offset = (index / shape * stride, index % shape * stride);
+        gather(input, input_width, width, offset, dilation, padding, window);
+    }
+    cols
+}
 
-        while y < input_width - width + 1 {
-            let mut filter_vector = vec![];
-            for i in 0..width {
-                for j in 0..width {
-                    let temp = matrix.get((x+i, y+j)).unwrap();
-                    filter_vector.push(*temp);
-                }
-            }
-            flipped_vector.push(filter_vector);
-            y += stride;
+pub fn col2im<T: Float>(cols: &Array2<T>, input_width: usize, width: usize, stride: usize, padding: usize, dilation: usize) -> Array2<T> {
+    // reverse of im2col: add every row of cols [out * out, f * f] back to the window it was taken from
+    // return [input_width, input_width]
+    // a pixel gets the windows in increasing order: the window index grows when the filter position decreases,
+    // so the columns are added from the last filter position to the first
+    let output_width = cal_shape(input_width, width, stride, padding, dilation);
+    let mut output: Array2<T> = Array2::zeros((input_width, input_width));
+    let data = output.as_slice_mut().unwrap();
+    let mut column = vec![T::zero(); output_width * output_width];
+
+    for position in (0..width * width).rev() {
+        for (c, &val) in column.iter_mut().zip(cols.column(position).iter()) {
+            *c = val;
         }
-        y = 0 as usize;
-        x += stride;
+        let offset = (position / width * dilation, position % width * dilation);
+        scatter_add(&column, output_width, offset, stride, padding, data, input_width);
     }
-    flipped_vector
+    output
 }
 
-pub fn im2col(matrix: &Array2<f32>, width: usize, stride: usize, padding: usize) -> Array2<f32> {
-    let flipped_as_nested_vector: Vec<Vec<f32>> = flip_matrix(&padding_input(matrix), width, stride);
-    let shape = cal_shape(matrix.shape()[0], width, stride, padding);
-
-    Array::from_shape_vec((shape * shape, width * width), 
-    flipped_as_nested_vector.into_iter().flatten().collect::<Vec<f32>>()).unwrap()
+pub fn dilated_width(width: usize, dilation: usize) -> usize {
+    // the width covered by a filter after inserting (dilation - 1) gaps between its elements
+    dilation * (width - 1) + 1
 }
 
-pub fn cal_shape(input_width: usize, filter_width: usize, stride: usize, padding: usize) -> usize {
-    (input_width - filter_width + 2 * padding) / stride + 1
+pub fn cal_shape(input_width: usize, filter_width: usize, stride: usize, padding: usize, dilation: usize) -> usize {
+    (input_width + 2 * padding - dilated_width(filter_width, dilation)) / stride + 1
 }
 
-pub fn _rotate(matrix: &Array2<f32>, degree: usize) ->  Array2<f32> {
+pub fn _rotate<T: Float>(matrix: &Array2<T>, degree: usize) ->  Array2<T> {
     // 1 == 90 degree( clockwise rotation)
     // 2 == 180 degree
     // 3 == 270 degree
     let length = matrix.shape()[0];
     let offset = length - 1;
 
-    let mut b: Vec<f32> = (0..length*length).map(|_| 0.).collect();
+    let mut b: Vec<T> = (0..length*length).map(|_| T::zero()).collect();
 
     for row in 0..length {
         for col in 0..length {
@@ -127,13 +129,13 @@ pub fn _restore_max_index(offset: usize, pair: (usize, usize), stride: usize, le
 // below functions for full connected layer
 ////////////////////////////////////////////////////////////
 /// 
-pub fn compute_loss(output: &Array2<f32>, labels: &Array2<f32>) -> f32 {
+pub fn compute_loss<T: Float>(output: &Array2<T>, labels: &Array2<T>) -> T {
     // output [sample, 10]
     // target [sample, 10]
-    let average = -1. / labels.shape()[0] as f32;
+    let average = -T::one() / T::from_usize(labels.shape()[0]);
 
     output.iter().zip(labels.iter())
-        .fold(0., |acc, (o, l)| acc + l * o.log(E)) * average
+        .fold(T::zero(), |acc, (&o, &l)| acc + l * o.ln()) * average
 }
 
 // pub fn compute_loss_single(output: &Vec<Array2<f32>>, labels: &Array2<f32>) -> f32 {
@@ -157,35 +159,21 @@ pub fn compute_loss(output: &Array2<f32>, labels: &Array2<f32>) -> f32 {
 //         }) * average
 // }
 
-pub fn evaluate(output: &Array2<f32>, labels: &Array2<f32>) -> f32 {
-    let predictions = output.map_axis(Axis(1), |row| {
-        let mut max = (0, 0.);
-        for (i, ele) in row.iter().enumerate() {
-            if *ele > max.1 {
-                max = (i, *ele);
-            }
-        }
-        max.0 as f32
-    });
-
-    let labels = labels.map_axis(Axis(1), |row| {
-        let mut max = (0, 0.);
-        for (i, ele) in row.iter().enumerate() {
-            if *ele > max.1 {
-                max = (i, *ele);
-            }
-        }
-        max.0 as f32
-    });
+pub fn evaluate<T: Float>(output: &Array2<T>, labels: &Array2<T>) -> T {
+    // output/labels [sample, classes], the output of a full layer is in column-major order
+    let classes = output.shape()[1];
+    let output = output.as_standard_layout();
+    let labels = labels.as_standard_layout();
+    let predictions = output.as_slice().unwrap().chunks(classes).map(argmax);
+    let labels = labels.as_slice().unwrap().chunks(classes).map(argmax);
 
     predictions
-        .into_iter()
-        .zip(labels.into_iter())
+        .zip(labels)
         .fold(
-            0.,
+            T::zero(),
             |acc, (prediction, label)| {
                 if prediction == label {
-                    acc + 1.
+                    acc + T::one()
                 } else {
                     acc
                 }
@@ -199,14 +187,19 @@ pub fn evaluate(output: &Array2<f32>, labels: &Array2<f32>) -> f32 {
 //////////////////////////
 
 
-pub fn _relu(input: &Array2<f32>) -> Array2<f32> {
-    input.mapv(|ele| if ele >= 0. { ele } else { 0. })
+pub fn _relu<T: Float>(input: &Array2<T>) -> Array2<T> {
+    input.mapv(|ele| if ele >= T::zero() { ele } else { T::zero() })
+}
+
+pub fn _relu_into<T: Float>(input: &Array2<T>, output: &mut Array2<T>) {
+    // same as _relu, but writes into output
+    output.zip_mut_with(input, |o, &ele| *o = if ele >= T::zero() { ele } else { T::zero() });
 }
 
-pub fn _softmax(input: &mut Array2<f32>) -> Vec<Array2<f32>> {
+pub fn _softmax<T: Float>(input: &mut Array2<T>) -> Vec<Array2<T>> {
     let exp_sum = input
         .map_axis(Axis(1), |row| {
-            row.fold(0., |acc, &ele: &f32| acc + ele.exp())
+            row.fold(T::zero(), |acc, &ele: &T| acc + ele.exp())
         })
         .into_shape((input.shape()[0], 1))
         .unwrap(); // [sample, 1]
@@ -215,6 +208,9 @@ pub fn _softmax(input: &mut Array2<f32>) -> Vec<Array2<f32>> {
     vec![exp_input / exp_sum]
 }
 
-pub fn relu_derivate(matrix: Array2<f32>) -> Array2<f32> {
-    matrix.mapv_into(|ele| if ele >= 0. {1.} else {0.})
+pub fn relu_derivate<T: Float>(input: &Array2<T>, delta: Array2<T>) -> Array2<T> {
+    // delta * relu'(input), the gradient only flows through the positive inputs
+    let mut delta = delta;
+    delta.zip_mut_with(input, |d, &ele| if ele <= T::zero() { *d = T::zero() });
+    delta
 }
\ No newline at end of file
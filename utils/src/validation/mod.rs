@@ -0,0 +1,84 @@
+use ndarray::Array2;
+use std::fmt::{self, Display, Formatter};
+
+use crate::dataset::{select, stratified_kfold};
+use crate::float::Float;
+use crate::network::{infer, nn};
+use crate::utils::utils::{compute_loss, evaluate};
+
+// k-fold cross-validation: for each stratified fold a new network is built and trained on the other folds,
+// then measured on the fold it has not seen
+// the mean and the standard deviation over the folds estimate how the architecture does on new data,
+// which a single validation split cannot tell apart from the luck of the split
+
+// accuracy and cross-entropy loss of a network on a validation fold
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics<T = f32> {
+    pub accuracy: T,
+    pub loss: T
+}
+
+pub struct CrossValidation<T = f32> {
+    pub folds: Vec<Metrics<T>>
+}
+
+impl<T: Float> CrossValidation<T> {
+    pub fn mean(&self) -> Metrics<T> {
+        let k = T::from_usize(self.folds.len());
+        Metrics {
+            accuracy: self.folds.iter().fold(T::zero(), |acc, m| acc + m.accuracy) / k,
+            loss: self.folds.iter().fold(T::zero(), |acc, m| acc + m.loss) / k
+        }
+    }
+
+    pub fn std(&self) -> Metrics<T> {
+        // population standard deviation over the folds
+        let k = T::from_usize(self.folds.len());
+        let mean = self.mean();
+        let variance = |value: fn(&Metrics<T>) -> T, mean: T| {
+            self.folds.iter().fold(T::zero(), |acc, m| acc + (value(m) - mean) * (value(m) - mean)) / k
+        };
+        Metrics {
+            accuracy: variance(|m| m.accuracy, mean.accuracy).sqrt(),
+            loss: variance(|m| m.loss, mean.loss).sqrt()
+        }
+    }
+}
+
+impl<T: Float> Display for CrossValidation<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, m) in self.folds.iter().enumerate() {
+            writeln!(f, "Fold#{:?}# Val-Acc: {:?} loss: {:?}", i, m.accuracy, m.loss)?;
+        }
+        let (mean, std) = (self.mean(), self.std());
+        writeln!(f, "Val-Acc: {:?} +- {:?} loss: {:?} +- {:?}", mean.accuracy, std.accuracy, mean.loss, std.loss)
+    }
+}
+
+// build: a new network, called once per fold
+// train: trains the network on the inputs and targets of the other folds (train_one_by_one, train_data_parallel, ...)
+pub fn cross_validate<T: Float, B, F>(
+    inputs: &[Vec<Array2<T>>],
+    labels: &Array2<T>,
+    k: usize,
+    seed: u64,
+    build: B,
+    train: F
+) -> CrossValidation<T>
+where B: Fn() -> Vec<nn<T>>, F: Fn(&mut Vec<nn<T>>, Vec<Vec<Array2<T>>>, Array2<T>) {
+    let folds = stratified_kfold(labels, k, seed).into_iter().enumerate().map(|(i, (train_indices, validation_indices))| {
+        println!("Starting #{:?}# Fold...", i);
+        let mut network = build();
+        let (train_inputs, train_labels) = select(inputs, labels, &train_indices);
+        train(&mut network, train_inputs, train_labels);
+
+        let (validation_inputs, validation_labels) = select(inputs, labels, &validation_indices);
+        let output = infer(&network, &validation_inputs);
+        Metrics {
+            accuracy: evaluate(&output[0][0], &validation_labels) / T::from_usize(validation_indices.len()),
+            loss: compute_loss(&output[0][0], &validation_labels)
+        }
+    }).collect::<Vec<Metrics<T>>>();
+
+    CrossValidation { folds }
+}
@@ -0,0 +1,83 @@
+use ndarray::Array2;
+use crate::float::Float;
+use crate::network::{nn, forward_with};
+use crate::propagation::Gradients;
+
+// scratch matrices a layer keeps between the calls of forward_into
+// e.g. the im2col matrix and the GEMM product of a conv layer
+pub struct Scratch<T = f32> {
+    pub cols: Array2<T>,
+    pub product: Array2<T>
+}
+
+impl<T: Float> Scratch<T> {
+    pub fn new() -> Scratch<T> {
+        Scratch {
+            cols: Array2::zeros((0, 0)),
+            product: Array2::zeros((0, 0))
+        }
+    }
+}
+
+impl<T: Float> Default for Scratch<T> {
+    fn default() -> Scratch<T> {
+        Scratch::new()
+    }
+}
+
+// buffers reused by every batch of a training run, only the first batch (or a batch with a new shape) allocates
+// activations[0] is the input of the network, activations[i + 1] is the output of network[i]
+// scratch[i] and gradients[i] belong to network[i]
+pub struct Workspace<T = f32> {
+    pub activations: Vec<Vec<Vec<Array2<T>>>>,
+    pub scratch: Vec<Scratch<T>>,
+    pub gradients: Vec<Gradients<T>>
+}
+
+impl<T: Float> Workspace<T> {
+    pub fn new(network: &Vec<nn<T>>, example: &[Vec<Array2<T>>]) -> Workspace<T> {
+        // example: a batch with the shape of the training batches
+        // the shapes of the buffers are inferred by running the network once on it
+        let mut workspace = Workspace {
+            activations: vec![vec![]; network.len() + 1],
+            scratch: network.iter().map(|_| Scratch::new()).collect(),
+            gradients: network.iter().map(|_| Gradients::empty(0)).collect()
+        };
+        forward_with(network, example, &mut workspace);
+        workspace
+    }
+
+    pub fn output(&self) -> &Vec<Vec<Array2<T>>> {
+        self.activations.last().unwrap()
+    }
+}
+
+pub fn resize<T: Float>(tensor: &mut Vec<Vec<Array2<T>>>, samples: usize, channels: usize, shape: (usize, usize)) {
+    // make tensor [samples, channels, shape], the arrays which already have the shape are kept
+    // the values are not reset, the caller overwrites them
+    tensor.resize_with(samples, Vec::new);
+    for sample in tensor.iter_mut() {
+        sample.resize_with(channels, || Array2::zeros(shape));
+        for arr in sample.iter_mut() {
+            resize_matrix(arr, shape);
+        }
+    }
+}
+
+pub fn resize_matrix<T: Float>(matrix: &mut Array2<T>, shape: (usize, usize)) {
+    if matrix.dim() != shape {
+        *matrix = Array2::zeros(shape);
+    }
+}
+
+pub fn assign<T: Float>(tensor: &mut Vec<Vec<Array2<T>>>, source: &[Vec<Array2<T>>]) {
+    // copy source into tensor, reusing the arrays of tensor
+    tensor.resize_with(source.len(), Vec::new);
+    for (sample, source_sample) in tensor.iter_mut().zip(source.iter()) {
+        sample.resize_with(source_sample.len(), || Array2::zeros((0, 0)));
+        for (arr, source_arr) in sample.iter_mut().zip(source_sample.iter()) {
+            resize_matrix(arr, source_arr.dim());
+            arr.assign(source_arr);
+        }
+    }
+}
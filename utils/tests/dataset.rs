@@ -0,0 +1,337 @@
+// the dataset parsers, samplers and transforms on small inputs written by the tests
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ndarray::{arr1, arr2, Array2};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use utils::dataset::{
+    load_cifar100_batches, load_csv, load_idx, load_mnist, read_idx, stratified_kfold, stratified_split, CsvOptions, DataLoader, DatasetError, GaussianNoise, HorizontalFlip,
+    Idx, ImageFolder, RandomAffine, RandomCrop, Resize, Sampler, Split, TensorDataset, Task, Transform, VerticalFlip
+};
+use utils::random::set_seed;
+
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    // unique per process, the tests run in parallel
+    let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn csv_schema() {
+    let train = temp_file("train.csv", b"x,color,y\n1,red,a\n2,blue,b\n3,red,a\n");
+    let test = temp_file("test.csv", b"color,y,x\nblue,b,3\nred,a,0\n");
+
+    let mut options = CsvOptions::new("y", Task::Classification);
+    options.categorical = vec!["color".to_string()];
+    let ((inputs, labels), schema) = load_csv(&train, &options).unwrap();
+    let (test_inputs, test_labels) = schema.load(&test).unwrap();
+    fs::remove_file(train).unwrap();
+    fs::remove_file(test).unwrap();
+
+    // x standardized with mean 2 and std sqrt(2/3), the categories in sorted order
+    let std = (2f32 / 3.).sqrt();
+    assert_eq!(schema.feature_names(), vec!["x", "color=blue", "color=red"]);
+    assert_eq!(inputs[0][0], arr2(&[[-1. / std, 0., 1.], [0., 1., 0.], [1. / std, 0., 1.]]));
+    assert_eq!(labels, arr2(&[[1., 0.], [0., 1.], [1., 0.]]));
+
+    // the columns are matched by name
+    assert_eq!(test_inputs[0][0], arr2(&[[1. / std, 1., 0.], [-2. / std, 0., 1.]]));
+    assert_eq!(test_labels, arr2(&[[0., 1.], [1., 0.]]));
+}
+
+#[test]
+fn csv_invalid_number() {
+    // only the listed columns are categorical, a missing number is an error
+    let path = temp_file("invalid.csv", b"x,y\n1,a\n,b\n");
+    let error = load_csv(&path, &CsvOptions::new("y", Task::Classification)).err().unwrap();
+    fs::remove_file(path).unwrap();
+    assert!(matches!(error, DatasetError::InvalidValue { ref column, ref value } if column == "x" && value.is_empty()));
+
+    // numbers that are not finite are errors too
+    for number in ["NaN", "inf", "-inf"].iter() {
+        let path = temp_file("not_finite.csv", format!("x,y\n1,a\n{},b\n", number).as_bytes());
+        let error = load_csv(&path, &CsvOptions::new("y", Task::Classification)).err().unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(matches!(error, DatasetError::InvalidValue { ref column, ref value } if column == "x" && value == number));
+    }
+}
+
+fn dataset(samples: usize) -> TensorDataset {
+    // sample i is a 2x2 image filled with i, its label is i
+    let inputs = (0..samples).map(|i| vec![Array2::from_elem((2, 2), i as f32)]).collect();
+    TensorDataset::new(inputs, Array2::from_shape_fn((samples, 1), |(i, _)| i as f32))
+}
+
+#[test]
+fn invalid_sampler_weights() {
+    let loader = DataLoader::new(dataset(3), 2, Sampler::Weighted { weights: vec![1., 2.], samples: 4 });
+    assert!(matches!(loader.iter().err().unwrap(), DatasetError::WeightCountMismatch { samples: 3, weights: 2 }));
+
+    let loader = DataLoader::new(dataset(3), 2, Sampler::Weighted { weights: vec![0., -1., 0.], samples: 4 });
+    assert!(matches!(loader.iter().err().unwrap(), DatasetError::InvalidWeights(_)));
+}
+
+#[test]
+fn workers_give_the_same_batches() {
+    // the order and the augmentation only depend on the seed
+    let batches = |workers: usize| {
+        let mut loader = DataLoader::new(dataset(10), 3, Sampler::Random);
+        loader.workers = workers;
+        loader.transform = Some(Arc::new(GaussianNoise::new(1.)));
+        set_seed(3);
+        loader.iter().unwrap().collect::<Result<Vec<Split>, DatasetError>>().unwrap()
+    };
+    let sequential = batches(0);
+    assert_eq!(sequential.len(), 4);
+    assert_eq!(batches(3), sequential);
+}
+
+#[test]
+fn dropping_batches_stops_the_workers() {
+    let mut loader = DataLoader::new(dataset(20), 1, Sampler::Sequential);
+    loader.workers = 2;
+    loader.prefetch = 1;
+    let mut batches = loader.iter().unwrap();
+    for label in 0..3 {
+        assert_eq!(batches.next().unwrap().unwrap().1[[0, 0]], label as f32);
+    }
+    // the workers are blocked on full channels, dropping must not wait for the whole epoch
+    drop(batches);
+}
+
+fn unbalanced_labels() -> (Array2<f32>, Vec<usize>) {
+    // 21 samples of 3 classes (9, 8 and 4 samples), interleaved
+    let classes = (0..21).map(|i| if i % 3 == 0 || i < 4 { 0 } else if i % 3 == 1 || i > 16 { 1 } else { 2 }).collect::<Vec<usize>>();
+    let labels = Array2::from_shape_fn((21, 3), |(i, c)| if classes[i] == c { 1. } else { 0. });
+    (labels, classes)
+}
+
+fn count(indices: &[usize], classes: &[usize], class: usize) -> f32 {
+    indices.iter().filter(|&&index| classes[index] == class).count() as f32
+}
+
+#[test]
+fn stratified_split_keeps_the_class_proportions() {
+    let (labels, classes) = unbalanced_labels();
+    let (train, validation) = stratified_split(&labels, 0.3, 7);
+
+    let mut all = [train.clone(), validation.clone()].concat();
+    all.sort_unstable();
+    assert_eq!(all, (0..21).collect::<Vec<usize>>());
+    for class in 0..3 {
+        let expected = count(&all, &classes, class) * 0.3;
+        assert!((count(&validation, &classes, class) - expected).abs() <= 1.);
+    }
+    assert_eq!(stratified_split(&labels, 0.3, 7), (train, validation));
+}
+
+#[test]
+fn stratified_kfold_covers_every_sample_once() {
+    let (labels, classes) = unbalanced_labels();
+    let folds = stratified_kfold(&labels, 4, 7);
+    assert_eq!(folds.len(), 4);
+
+    let mut validations = folds.iter().flat_map(|(_, validation)| validation.iter().cloned()).collect::<Vec<usize>>();
+    validations.sort_unstable();
+    assert_eq!(validations, (0..21).collect::<Vec<usize>>());
+    for (train, validation) in folds.iter() {
+        let mut all = [train.clone(), validation.clone()].concat();
+        all.sort_unstable();
+        assert_eq!(all, (0..21).collect::<Vec<usize>>());
+        for class in 0..3 {
+            let expected = count(&validations, &classes, class) / 4.;
+            assert!((count(validation, &classes, class) - expected).abs() <= 1.);
+        }
+    }
+    assert_eq!(stratified_kfold(&labels, 4, 7), folds);
+}
+
+#[test]
+fn image_folder_channels() {
+    // checked before the directory is read
+    let error = ImageFolder::new(std::env::temp_dir(), 8, 8, 2, Resize::Stretch).err().unwrap();
+    assert!(matches!(error, DatasetError::ShapeMismatch { ref found, .. } if *found == vec![2]));
+}
+
+fn image() -> Vec<Array2<f32>> {
+    // 2 channels of 3x4, every pixel different
+    (0..2).map(|c| Array2::from_shape_fn((3, 4), |(i, j)| (c * 12 + i * 4 + j) as f32)).collect()
+}
+
+#[test]
+fn flips_are_involutions() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let transforms: Vec<Box<dyn Transform>> = vec![Box::new(HorizontalFlip::new(1.)), Box::new(VerticalFlip::new(1.))];
+    for transform in transforms.iter() {
+        let flipped = transform.apply(image(), &mut rng);
+        assert_ne!(flipped, image());
+        assert_eq!(transform.apply(flipped, &mut rng), image());
+    }
+}
+
+#[test]
+fn full_size_crop_is_the_identity() {
+    let mut rng = StdRng::seed_from_u64(1);
+    assert_eq!(RandomCrop::new(3, 4, 0).apply(image(), &mut rng), image());
+}
+
+#[test]
+fn transforms_depend_only_on_the_seed() {
+    let affine = RandomAffine::new(30., 0.2, (0.8, 1.2), 10.);
+    let crop = RandomCrop::new(3, 4, 2);
+    let apply = |transform: &dyn Transform, seed: u64| transform.apply(image(), &mut StdRng::seed_from_u64(seed));
+    assert_eq!(apply(&affine, 4), apply(&affine, 4));
+    assert_ne!(apply(&affine, 4), apply(&affine, 5));
+    assert_eq!(apply(&crop, 4), apply(&crop, 4));
+}
+
+#[test]
+#[should_panic(expected = "positive zooms")]
+fn affine_rejects_a_zero_scale() {
+    RandomAffine::new(0., 0., (0., 1.), 0.);
+}
+
+fn idx(dtype: u8, dims: &[u32], data: &[u8]) -> Vec<u8> {
+    // magic number, big-endian dimensions, then the data
+    let mut bytes = vec![0, 0, dtype, dims.len() as u8];
+    bytes.extend(dims.iter().flat_map(|dim| dim.to_be_bytes().to_vec()));
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[test]
+fn idx_data_types() {
+    let read = |bytes: Vec<u8>| read_idx(&mut Cursor::new(bytes)).unwrap();
+
+    match read(idx(0x08, &[2, 3], &[0, 1, 2, 3, 4, 255])) {
+        Idx::U8(data) => assert_eq!(data.into_shape((2, 3)).unwrap(), arr2(&[[0, 1, 2], [3, 4, 255]])),
+        other => panic!("u8 read as {}", other.dtype()),
+    }
+    match read(idx(0x09, &[2], &[0xff, 0x01])) {
+        Idx::I8(data) => assert_eq!(data.into_shape(2).unwrap(), arr1(&[-1, 1])),
+        other => panic!("i8 read as {}", other.dtype()),
+    }
+    match read(idx(0x0B, &[2], &[&(-2i16).to_be_bytes()[..], &300i16.to_be_bytes()].concat())) {
+        Idx::I16(data) => assert_eq!(data.into_shape(2).unwrap(), arr1(&[-2, 300])),
+        other => panic!("i16 read as {}", other.dtype()),
+    }
+    match read(idx(0x0C, &[1], &(-70000i32).to_be_bytes())) {
+        Idx::I32(data) => assert_eq!(data.into_shape(1).unwrap(), arr1(&[-70000])),
+        other => panic!("i32 read as {}", other.dtype()),
+    }
+    match read(idx(0x0D, &[2], &[1.5f32.to_be_bytes(), (-0.25f32).to_be_bytes()].concat())) {
+        Idx::F32(data) => assert_eq!(data.into_shape(2).unwrap(), arr1(&[1.5, -0.25])),
+        other => panic!("f32 read as {}", other.dtype()),
+    }
+    match read(idx(0x0E, &[1, 1, 1], &2.5f64.to_be_bytes())) {
+        Idx::F64(data) => assert_eq!(data.shape(), &[1, 1, 1]),
+        other => panic!("f64 read as {}", other.dtype()),
+    }
+}
+
+#[test]
+fn idx_errors() {
+    let read = |bytes: Vec<u8>| read_idx(&mut Cursor::new(bytes)).err().unwrap();
+
+    assert!(matches!(read(idx(0x08, &[4], &[1, 2])), DatasetError::Truncated { expected: 4, found: 2 }));
+    // the header itself cut short
+    assert!(matches!(read(vec![0, 0, 0x08, 2, 0, 0]), DatasetError::Truncated { expected: 4, found: 2 }));
+    assert!(matches!(read(vec![1, 0, 0x08, 1]), DatasetError::BadMagic([1, 0, 0x08, 1])));
+    assert!(matches!(read(idx(0x0A, &[1], &[0])), DatasetError::BadMagic(_)));
+}
+
+#[test]
+fn mnist_files() {
+    let paths = [
+        temp_file("images", &idx(0x08, &[2, 1, 2], &[0, 255, 51, 102])),
+        temp_file("labels", &idx(0x08, &[2], &[3, 7])),
+        temp_file("test-images", &idx(0x08, &[1, 1, 2], &[255, 0])),
+        temp_file("test-labels", &idx(0x08, &[1], &[1]))
+    ];
+    let ((images, labels), (test_images, test_labels)) = load_mnist(paths.clone()).unwrap();
+
+    // one image but two labels in the test files
+    fs::write(&paths[3], idx(0x08, &[2], &[1, 2])).unwrap();
+    let error = load_mnist(paths.clone()).err().unwrap();
+    // a label that is not one of the 10 classes
+    fs::write(&paths[3], idx(0x08, &[1], &[10])).unwrap();
+    let out_of_range = load_mnist(paths.clone()).err().unwrap();
+    for path in paths.iter() {
+        fs::remove_file(path).unwrap();
+    }
+
+    // the u8 pixels scaled to [0, 1], the labels one-hot over 10 classes
+    assert_eq!(images, vec![vec![arr2(&[[0., 1.]])], vec![arr2(&[[0.2, 0.4]])]]);
+    assert_eq!(labels.dim(), (2, 10));
+    assert_eq!((labels[[0, 3]], labels[[1, 7]], labels.sum()), (1., 1., 2.));
+    assert_eq!(test_images, vec![vec![arr2(&[[1., 0.]])]]);
+    assert_eq!(test_labels[[0, 1]], 1.);
+    assert!(matches!(error, DatasetError::LabelCountMismatch { samples: 1, labels: 2 }));
+    assert!(matches!(out_of_range, DatasetError::LabelOutOfRange { label: 10, classes: 10 }));
+}
+
+#[test]
+fn gzip_idx() {
+    let bytes = idx(0x08, &[100], &(0..100).collect::<Vec<u8>>());
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&bytes).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    // detected by the magic number, not the extension
+    let path = temp_file("gzip.idx", &compressed);
+    let loaded = load_idx(&path).unwrap().to_f32();
+    // a stream cut in the middle of the data
+    fs::write(&path, &compressed[..compressed.len() / 2]).unwrap();
+    let truncated = load_idx(&path).err().unwrap();
+    // a complete stream whose first deflate block has the reserved block type 11
+    let mut corrupted = compressed.clone();
+    corrupted[10] |= 0b110;
+    fs::write(&path, &corrupted).unwrap();
+    let corrupt = load_idx(&path).err().unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.into_shape(100).unwrap(), (0..100).map(|x| x as f32).collect::<ndarray::Array1<f32>>());
+    assert!(matches!(truncated, DatasetError::Truncated { expected: 100, .. }));
+    assert!(matches!(corrupt, DatasetError::Io(_)), "{:?}", corrupt);
+}
+
+fn cifar100_record(coarse: u8, fine: u8, red: u8, green: u8, blue: u8) -> Vec<u8> {
+    // the first pixel of every channel set, the others 0
+    let mut record = vec![0; 2 + 3 * 1024];
+    record[0] = coarse;
+    record[1] = fine;
+    record[2] = red;
+    record[2 + 1024 + 1] = green;
+    record[2 + 2 * 1024 + 32] = blue;
+    record
+}
+
+#[test]
+fn cifar100_records() {
+    let records = [cifar100_record(3, 42, 255, 51, 102), cifar100_record(19, 99, 0, 0, 255)].concat();
+    let path = temp_file("cifar100.bin", &records);
+    let (images, coarse, fine) = load_cifar100_batches(&[&path]).unwrap();
+
+    // a label out of range, then a record cut short
+    fs::write(&path, cifar100_record(20, 0, 0, 0, 0)).unwrap();
+    let out_of_range = load_cifar100_batches(&[&path]).err().unwrap();
+    fs::write(&path, &cifar100_record(0, 0, 0, 0, 0)[..1000]).unwrap();
+    let truncated = load_cifar100_batches(&[&path]).err().unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!((images.len(), images[0].len(), images[0][0].dim()), (2, 3, (32, 32)));
+    assert_eq!((images[0][0][[0, 0]], images[0][1][[0, 1]], images[0][2][[1, 0]]), (1., 0.2, 0.4));
+    assert_eq!(images[0].iter().map(|channel| channel.sum()).sum::<f32>(), 1.6);
+    assert_eq!(images[1][2][[1, 0]], 1.);
+
+    assert_eq!((coarse.dim(), fine.dim()), ((2, 20), (2, 100)));
+    assert_eq!((coarse[[0, 3]], coarse[[1, 19]], coarse.sum()), (1., 1., 2.));
+    assert_eq!((fine[[0, 42]], fine[[1, 99]], fine.sum()), (1., 1., 2.));
+    assert!(matches!(out_of_range, DatasetError::LabelOutOfRange { label: 20, classes: 20 }));
+    assert!(matches!(truncated, DatasetError::Truncated { expected: 3074, found: 1000 }));
+}
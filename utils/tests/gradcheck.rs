@@ -0,0 +1,201 @@
+// gradient checks of every layer, in f64 so the central differences are accurate
+use ndarray::Array2;
+use utils::activation::Activation;
+use utils::convolution::{Conv3D, ConvConfig, ConvTranspose2D, SeparableConv};
+use utils::full_connected::FullLayer;
+use utils::gradcheck::{gradcheck, squared_error, cross_entropy};
+use utils::network::nn;
+use utils::normalization::{GroupNorm, LayerNorm};
+use utils::pooling::Pool;
+use utils::upsampling::Upsample;
+
+const TOLERANCE: f64 = 1e-6;
+const ALPHA: f64 = 0.1;
+
+fn matrix(rows: usize, cols: usize, seed: usize) -> Array2<f64> {
+    // deterministic values in [-1, 1)
+    Array2::from_shape_fn((rows, cols), |(i, j)| {
+        let x = ((i * cols + j) as f64 * 12.9898 + seed as f64 * 78.233).sin() * 43758.5453;
+        (x - x.floor()) * 2. - 1.
+    })
+}
+
+fn tensor(samples: usize, channels: usize, width: usize, seed: usize) -> Vec<Vec<Array2<f64>>> {
+    (0..samples).map(|s| {
+        (0..channels).map(|c| matrix(width, width, seed + s * channels + c)).collect::<Vec<Array2<f64>>>()
+    }).collect::<Vec<Vec<Array2<f64>>>>()
+}
+
+fn away_from_zero(input: Vec<Vec<Array2<f64>>>) -> Vec<Vec<Array2<f64>>> {
+    // relu is not differentiable at 0
+    input.into_iter().map(|channels| {
+        channels.into_iter().map(|arr| arr.mapv(|x| if x.abs() < 0.1 { x + 0.2 } else { x })).collect()
+    }).collect()
+}
+
+fn randomize(network: &Vec<nn<f64>>, seed: usize) {
+    // deterministic parameters instead of the random initialization, scaled so the activations stay moderate
+    for (l, layer) in network.iter().enumerate() {
+        let parameters = layer.parameters().iter().enumerate().map(|(i, p)| {
+            matrix(p.shape()[0], p.shape()[1], seed + l * 10 + i) * 0.5
+        }).collect::<Vec<Array2<f64>>>();
+        layer.set_parameters(&parameters);
+    }
+}
+
+fn check(network: Vec<nn<f64>>, input: Vec<Vec<Array2<f64>>>) {
+    // squared error against a target of the shape of the output
+    randomize(&network, 100);
+    let output = utils::network::forward(&network, &input).pop().unwrap();
+    let target = output.iter().enumerate().map(|(s, channels)| {
+        channels.iter().enumerate().map(|(c, arr)| matrix(arr.shape()[0], arr.shape()[1], 500 + s * 10 + c))
+            .collect::<Vec<Array2<f64>>>()
+    }).collect::<Vec<Vec<Array2<f64>>>>();
+
+    let result = gradcheck(&network, &input, |output| squared_error(output, &target));
+    assert!(result.max_error() < TOLERANCE, "\n{}", result);
+}
+
+#[test]
+fn conv() {
+    check(vec![nn::Conv(Conv3D::new(ConvConfig::new(2, 3, 1, 1, 6, 3), ALPHA))], tensor(2, 2, 6, 1));
+}
+
+#[test]
+fn conv_strided() {
+    check(vec![nn::Conv(Conv3D::new(ConvConfig::new(2, 3, 2, 1, 7, 3), ALPHA))], tensor(2, 2, 7, 2));
+    check(vec![nn::Conv(Conv3D::new(ConvConfig::new(1, 2, 3, 0, 8, 2), ALPHA))], tensor(3, 1, 8, 3));
+}
+
+#[test]
+fn conv_dilated_grouped() {
+    check(vec![nn::Conv(Conv3D::new(ConvConfig::new(4, 4, 1, 2, 6, 3).dilation(2).groups(2), ALPHA))], tensor(2, 4, 6, 4));
+}
+
+#[test]
+#[should_panic(expected = "a convolution needs at least 1 group")]
+fn conv_rejects_zero_groups() {
+    Conv3D::new(ConvConfig::new(4, 4, 1, 1, 6, 3).groups(0), ALPHA);
+}
+
+#[test]
+fn separable_conv() {
+    check(vec![nn::Separable(SeparableConv::new(ConvConfig::new(2, 3, 1, 1, 5, 3), ALPHA))], tensor(2, 2, 5, 5));
+}
+
+#[test]
+fn conv_transpose() {
+    check(vec![nn::ConvTranspose(ConvTranspose2D::new(2, 3, 2, 1, 1, 4, 3, ALPHA))], tensor(2, 2, 4, 6));
+}
+
+#[test]
+fn pool() {
+    // distinct values, no ties inside a window
+    let input = (0..2).map(|s| {
+        (0..2).map(|c| Array2::from_shape_fn((6, 6), |(i, j)| {
+            (((i * 6 + j) * 7 + s * 3 + c * 5) % 36) as f64 / 36.
+        })).collect::<Vec<Array2<f64>>>()
+    }).collect::<Vec<Vec<Array2<f64>>>>();
+    check(vec![nn::Pool(Pool::new(2, 2, 0, 2, 6, 0))], input);
+}
+
+#[test]
+fn upsample() {
+    check(vec![nn::Upsample(Upsample::new(2, 3, 0))], tensor(2, 2, 3, 7));
+    check(vec![nn::Upsample(Upsample::new(2, 3, 1))], tensor(2, 2, 3, 8));
+}
+
+#[test]
+fn relu() {
+    check(vec![nn::Activation(Activation::new(0))], away_from_zero(tensor(2, 2, 4, 9)));
+}
+
+#[test]
+fn softmax() {
+    let input = vec![vec![matrix(3, 5, 10)]];
+    let mut labels = Array2::zeros((3, 5));
+    for s in 0..3 {
+        labels[[s, s]] = 1.;
+    }
+    let network = vec![nn::Activation(Activation::new(1))];
+    let result = gradcheck(&network, &input, |output| cross_entropy(output, &labels));
+    assert!(result.max_error() < TOLERANCE, "\n{}", result);
+}
+
+#[test]
+fn full() {
+    check(vec![nn::Full(FullLayer::new(4, 6, ALPHA, 0))], vec![vec![matrix(3, 6, 11)]]);
+    // connected with a conv/pool layer: 2 channels of 3x3
+    check(vec![nn::Full(FullLayer::new(4, 18, ALPHA, 2))], tensor(3, 2, 3, 12));
+}
+
+#[test]
+fn group_norm() {
+    check(vec![nn::GroupNorm(GroupNorm::new(2, 4, ALPHA, 0))], tensor(2, 4, 3, 13));
+    check(vec![nn::GroupNorm(GroupNorm::new(2, 6, ALPHA, 1))], vec![vec![matrix(3, 6, 14)]]);
+}
+
+#[test]
+#[should_panic(expected = "GroupNorm needs at least 1 group")]
+fn group_norm_rejects_zero_groups() {
+    GroupNorm::new(0, 4, ALPHA, 0);
+}
+
+#[test]
+fn layer_norm() {
+    check(vec![nn::LayerNorm(LayerNorm::new(3, ALPHA, 0))], tensor(2, 3, 3, 15));
+    check(vec![nn::LayerNorm(LayerNorm::new(5, ALPHA, 1))], vec![vec![matrix(3, 5, 16)]]);
+}
+
+#[test]
+fn network() {
+    // the layout of network/src/main.rs on small inputs
+    let network = vec![
+        nn::new("Conv".to_string(), vec![1, 3, 1, 1, 8, 3, 0], ALPHA),
+        nn::new("Relu".to_string(), vec![0], ALPHA),
+        nn::new("Pool".to_string(), vec![2, 2, 0, 3, 8, 0], ALPHA),
+        nn::new("Full".to_string(), vec![6, 48, 3], ALPHA),
+        nn::new("Relu".to_string(), vec![0], ALPHA),
+        nn::new("Full".to_string(), vec![4, 6, 0], ALPHA),
+        nn::new("Softmax".to_string(), vec![1], ALPHA)
+    ];
+    randomize(&network, 200);
+    let input = tensor(2, 1, 8, 17);
+    let mut labels = Array2::zeros((2, 4));
+    labels[[0, 1]] = 1.;
+    labels[[1, 3]] = 1.;
+
+    let result = gradcheck(&network, &input, |output| cross_entropy(output, &labels));
+    assert!(result.max_error() < TOLERANCE, "\n{}", result);
+}
+
+#[test]
+fn updates_use_the_mean_gradient() {
+    // apply steps every parameter by alpha * derivate / samples
+    let layers = vec![
+        (nn::Conv(Conv3D::new(ConvConfig::new(2, 3, 2, 1, 5, 3), ALPHA)), tensor(3, 2, 5, 20)),
+        (nn::Separable(SeparableConv::new(ConvConfig::new(2, 3, 1, 1, 5, 3), ALPHA)), tensor(3, 2, 5, 21)),
+        (nn::ConvTranspose(ConvTranspose2D::new(2, 3, 2, 1, 1, 3, 3, ALPHA)), tensor(3, 2, 3, 22)),
+        (nn::Full(FullLayer::new(4, 6, ALPHA, 0)), vec![vec![matrix(3, 6, 23)]]),
+        (nn::GroupNorm(GroupNorm::new(2, 4, ALPHA, 0)), tensor(3, 4, 3, 24)),
+        (nn::LayerNorm(LayerNorm::new(3, ALPHA, 1)), vec![vec![matrix(3, 3, 25)]])
+    ];
+
+    for (layer, input) in layers {
+        let output = layer.forward(&input);
+        let deltas = output.iter().map(|channels| channels.iter().map(|arr| arr.mapv(|x| x - 0.3)).collect()).collect();
+        let (_, gradients) = layer.gradients(&input, deltas);
+
+        let before = layer.parameters();
+        layer.apply(&gradients);
+        let after = layer.parameters();
+
+        for ((b, a), d) in before.iter().zip(after.iter()).zip(gradients.derivates.iter()) {
+            let expected = d * ALPHA / gradients.samples as f64;
+            let step = b - a;
+            for (s, e) in step.iter().zip(expected.iter()) {
+                assert!((s - e).abs() < 1e-12, "step {} expected {}", s, e);
+            }
+        }
+    }
+}
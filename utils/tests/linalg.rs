@@ -0,0 +1,121 @@
+// the GEMMs must give the same results with and without the blas feature:
+// cargo test -p utils --test linalg
+// cargo test -p utils --test linalg --features blas
+use ndarray::{s, Array2};
+use utils::convolution::{Conv3D, ConvConfig};
+use utils::float::Float;
+use utils::linalg::{dot, gemm};
+use utils::propagation::Propagation;
+use utils::utils::_convolution;
+
+fn matrix<T: Float>(rows: usize, cols: usize, seed: usize) -> Array2<T> {
+    Array2::from_shape_fn((rows, cols), |(i, j)| T::from_f32(((i * 31 + j * 17 + seed * 7) % 23) as f32 / 23. - 0.5))
+}
+
+fn naive<T: Float>(a: &Array2<T>, b: &Array2<T>) -> Array2<T> {
+    Array2::from_shape_fn((a.shape()[0], b.shape()[1]), |(i, j)| {
+        (0..a.shape()[1]).fold(T::zero(), |acc, k| acc + a[[i, k]] * b[[k, j]])
+    })
+}
+
+fn assert_close<T: Float>(result: &Array2<T>, expected: &Array2<T>, tolerance: T) {
+    assert_eq!(result.shape(), expected.shape());
+    for (r, e) in result.iter().zip(expected.iter()) {
+        assert!((*r - *e).abs() <= tolerance * (T::one() + e.abs()), "{} != {}", r, e);
+    }
+}
+
+fn check_dot<T: Float>(tolerance: T) {
+    let a = matrix::<T>(13, 40, 1);
+    let b = matrix::<T>(40, 9, 2);
+    let expected = naive(&a, &b);
+
+    // contiguous
+    assert_close(&dot(&a, &b), &expected, tolerance);
+
+    // transposed operands
+    let at = a.t().to_owned();
+    let bt = b.t().to_owned();
+    assert_close(&dot(&at.t(), &bt.t()), &expected, tolerance);
+
+    // slices of bigger matrices
+    let big_a = matrix::<T>(20, 50, 3);
+    let big_b = matrix::<T>(45, 30, 4);
+    let (sa, sb) = (big_a.slice(s![3..16, 5..45]), big_b.slice(s![2..42, 10..19]));
+    assert_close(&dot(&sa, &sb), &naive(&sa.to_owned(), &sb.to_owned()), tolerance);
+
+    // strided views can not be passed to BLAS and use the fallback
+    let (sa, sb) = (big_a.slice(s![..;2, ..40]), big_b.slice(s![..40, ..;3]));
+    assert_close(&dot(&sa, &sb), &naive(&sa.to_owned(), &sb.to_owned()), tolerance);
+}
+
+fn check_gemm<T: Float>(tolerance: T) {
+    let a = matrix::<T>(7, 11, 5);
+    let b = matrix::<T>(11, 6, 6);
+    let mut c = matrix::<T>(7, 6, 7);
+    let expected = naive(&a, &b) * T::from_f32(0.5) + &c * T::from_f32(2.);
+
+    gemm(T::from_f32(0.5), &a, &b, T::from_f32(2.), &mut c);
+    assert_close(&c, &expected, tolerance);
+
+    // into the rows of a bigger matrix
+    let mut big = matrix::<T>(10, 6, 8);
+    let mut expected_big = big.clone();
+    expected_big.slice_mut(s![2..9, ..]).assign(&naive(&a, &b));
+    gemm(T::one(), &a, &b, T::zero(), &mut big.slice_mut(s![2..9, ..]));
+    assert_close(&big, &expected_big, tolerance);
+}
+
+fn check_conv<T: Float>(tolerance: T) {
+    // per-channel convolutions as the reference of the batched GEMM of Conv3D
+    let (in_channel, out_channel, width, filter) = (3, 4, 9, 3);
+    let conv = Conv3D::<T>::new(ConvConfig::new(in_channel, out_channel, 2, 1, width, filter), T::from_f32(0.1));
+    *conv.bias.write().unwrap() = matrix::<T>(out_channel, 1, 9);
+    let inputs = (0..2).map(|sample| {
+        (0..in_channel).map(|c| matrix::<T>(width, width, sample * in_channel + c)).collect::<Vec<Array2<T>>>()
+    }).collect::<Vec<Vec<Array2<T>>>>();
+
+    let outputs = conv.forward(&inputs);
+    let weights = conv.weights.read().unwrap();
+    let bias = conv.bias.read().unwrap();
+    for (input, output) in inputs.iter().zip(outputs.iter()) {
+        for out in 0..out_channel {
+            let expected = input.iter().enumerate().fold(Array2::from_elem(output[out].raw_dim(), bias[[out, 0]]), |acc, (c, arr)| {
+                let kernel = weights.slice(s![out, c * filter * filter..(c + 1) * filter * filter])
+                    .to_owned().into_shape((filter, filter)).unwrap();
+                acc + _convolution(&kernel, arr, 2, 1, 1)
+            });
+            assert_close(&output[out], &expected, tolerance);
+        }
+    }
+}
+
+#[test]
+fn dot_f32() {
+    check_dot::<f32>(1e-5);
+}
+
+#[test]
+fn dot_f64() {
+    check_dot::<f64>(1e-12);
+}
+
+#[test]
+fn gemm_f32() {
+    check_gemm::<f32>(1e-5);
+}
+
+#[test]
+fn gemm_f64() {
+    check_gemm::<f64>(1e-12);
+}
+
+#[test]
+fn conv_f32() {
+    check_conv::<f32>(1e-5);
+}
+
+#[test]
+fn conv_f64() {
+    check_conv::<f64>(1e-12);
+}
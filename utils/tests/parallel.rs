@@ -0,0 +1,55 @@
+// the data parallel training must not depend on the number of workers
+use ndarray::Array2;
+use utils::network::nn;
+use utils::parallel::train_data_parallel;
+
+fn network() -> Vec<nn<f64>> {
+    vec![
+        nn::new("Conv".to_string(), vec![1, 2, 1, 1, 6, 3, 0], 0.1),
+        nn::new("Relu".to_string(), vec![0], 0.1),
+        nn::new("Pool".to_string(), vec![2, 2, 0, 2, 6, 1], 0.1),
+        nn::new("Full".to_string(), vec![3, 18, 2], 0.1),
+        nn::new("Softmax".to_string(), vec![1], 0.1)
+    ]
+}
+
+fn copy(network: &[nn<f64>]) -> Vec<nn<f64>> {
+    let copy = self::network();
+    for (layer, source) in copy.iter().zip(network.iter()) {
+        layer.set_parameters(&source.parameters());
+    }
+    copy
+}
+
+fn data(samples: usize) -> (Vec<Vec<Array2<f64>>>, Array2<f64>) {
+    let inputs = (0..samples).map(|s| {
+        vec![Array2::from_shape_fn((6, 6), |(i, j)| ((s * 36 + i * 6 + j) as f64 * 0.37).sin())]
+    }).collect::<Vec<Vec<Array2<f64>>>>();
+    let labels = Array2::from_shape_fn((samples, 3), |(s, c)| if s % 3 == c { 1. } else { 0. });
+    (inputs, labels)
+}
+
+#[test]
+fn workers_give_the_same_parameters() {
+    // 11 samples: the last batch is short and so is its last shard
+    let (inputs, labels) = data(11);
+    let initial = network();
+    let mut single = copy(&initial);
+    train_data_parallel(&mut single, 1, 4, 2, 1, inputs.clone(), labels.clone(), None);
+    assert_ne!(single[0].parameters(), initial[0].parameters());
+
+    for &workers in &[2, 3] {
+        let mut parallel = copy(&initial);
+        train_data_parallel(&mut parallel, 1, 4, 2, workers, inputs.clone(), labels.clone(), None);
+        for (a, b) in single.iter().zip(parallel.iter()) {
+            assert_eq!(a.parameters(), b.parameters());
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "batch_size and shard_size must be at least 1")]
+fn empty_shards_are_rejected() {
+    let (inputs, labels) = data(4);
+    train_data_parallel(&mut network(), 1, 4, 0, 2, inputs, labels, None);
+}
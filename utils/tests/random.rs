@@ -0,0 +1,26 @@
+// the random draws must only depend on the seed, not on what was printed or logged in between
+// the generator is global: no other test of this file may draw from it
+use utils::network::nn;
+use utils::random::{normal, set_seed};
+
+#[test]
+fn formatting_layers_does_not_draw() {
+    let network: Vec<nn<f32>> = vec![
+        nn::new("Conv".to_string(), vec![1, 2, 1, 1, 6, 3, 0], 0.1),
+        nn::new("Separable".to_string(), vec![2, 4, 1, 1, 6, 3, 0], 0.1),
+        nn::new("ConvTranspose".to_string(), vec![4, 2, 2, 1, 0, 6, 3], 0.1),
+        nn::new("Relu".to_string(), vec![0], 0.1),
+        nn::new("Pool".to_string(), vec![2, 2, 0, 2, 6, 1], 0.1),
+        nn::new("Upsample".to_string(), vec![2, 3, 0], 0.1),
+        nn::new("GroupNorm".to_string(), vec![2, 4, 0], 0.1),
+        nn::new("LayerNorm".to_string(), vec![4, 0], 0.1),
+        nn::new("Full".to_string(), vec![3, 18, 2], 0.1)
+    ];
+
+    set_seed(8);
+    let expected = normal::<f32>((2, 2));
+    set_seed(8);
+    let text = network.iter().map(|layer| format!("{}", layer)).collect::<Vec<String>>();
+    assert_eq!(normal::<f32>((2, 2)), expected);
+    assert_eq!(text[8], "layer Full 18 -> 3 neurons");
+}
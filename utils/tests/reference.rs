@@ -0,0 +1,304 @@
+// the first implementations of the layers, kept as references of the rewritten ones
+// cargo test -p utils --test reference
+use ndarray::{s, stack, Array, Array2, ArrayView1, Axis};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use utils::convolution::{Conv3D, ConvConfig};
+use utils::propagation::Propagation;
+use utils::utils::{_col2im_channels, _im2col_channels, _max_pool, _max_pool_values};
+use utils::utils::utils::{col2im, evaluate, im2col};
+
+fn matrix(rows: usize, cols: usize, seed: usize) -> Array2<f32> {
+    // deterministic values in [-1, 1)
+    Array2::from_shape_fn((rows, cols), |(i, j)| {
+        let x = ((i * cols + j) as f32 * 12.9898 + seed as f32 * 78.233).sin() * 43758.5453;
+        (x - x.floor()) * 2. - 1.
+    })
+}
+
+fn tensor(samples: usize, channels: usize, width: usize, seed: usize) -> Vec<Vec<Array2<f32>>> {
+    (0..samples).map(|s| {
+        (0..channels).map(|c| matrix(width, width, seed + s * channels + c)).collect::<Vec<Array2<f32>>>()
+    }).collect::<Vec<Vec<Array2<f32>>>>()
+}
+
+fn assert_close(result: &Array2<f32>, expected: &Array2<f32>) {
+    // the GEMMs sum the channels in another order
+    assert_eq!(result.shape(), expected.shape());
+    for (r, e) in result.iter().zip(expected.iter()) {
+        assert!((r - e).abs() <= 1e-5 * (1. + e.abs()), "{} != {}", r, e);
+    }
+}
+
+// Conv2D before dilation: the input is always padded by 1, whatever the padding,
+// and the deltas of the input are the convolution of the padded deltas with the filter rotated by 180 degrees,
+// which is only right for stride 1 and padding == filter_width - 2
+
+fn padding_input(matrix: &Array2<f32>) -> Array2<f32> {
+    let width = matrix.shape()[0];
+    let mut padded = Array::zeros((width + 2, width + 2));
+    padded.slice_mut(s![1..width + 1, 1..width + 1]).assign(matrix);
+    padded
+}
+
+fn flip_matrix(matrix: &Array2<f32>, width: usize, stride: usize) -> Vec<Vec<f32>> {
+    let input_width = matrix.shape()[0];
+    let mut flipped_vector = vec![];
+    let mut x = 0;
+    let mut y = 0;
+
+    while x < input_width - width + 1 {
+        while y < input_width - width + 1 {
+            let mut filter_vector = vec![];
+            for i in 0..width {
+                for j in 0..width {
+                    filter_vector.push(matrix[[x + i, y + j]]);
+                }
+            }
+            flipped_vector.push(filter_vector);
+            y += stride;
+        }
+        y = 0;
+        x += stride;
+    }
+    flipped_vector
+}
+
+fn cal_shape(input_width: usize, filter_width: usize, stride: usize, padding: usize) -> usize {
+    (input_width - filter_width + 2 * padding) / stride + 1
+}
+
+fn convolution(filter: &Array2<f32>, input: &Array2<f32>, stride: usize, padding: usize) -> Array2<f32> {
+    let filter_width = filter.shape()[0];
+    let output_width = cal_shape(input.shape()[0], filter_width, stride, padding);
+    let cols = flip_matrix(&padding_input(input), filter_width, stride).into_iter().flatten().collect::<Vec<f32>>();
+    let cols = Array2::from_shape_vec((output_width * output_width, filter_width * filter_width), cols).unwrap();
+    let filter = Array2::from_shape_vec((filter_width * filter_width, 1), filter.iter().copied().collect()).unwrap();
+
+    cols.dot(&filter).into_shape((output_width, output_width)).unwrap()
+}
+
+fn rotate(matrix: &Array2<f32>) -> Array2<f32> {
+    let width = matrix.shape()[0];
+    Array2::from_shape_fn((width, width), |(i, j)| matrix[[width - 1 - i, width - 1 - j]])
+}
+
+fn kernel(conv: &Conv3D, out: usize, c: usize) -> Array2<f32> {
+    let f = conv.filter_width;
+    conv.weights.read().unwrap().slice(s![out, c * f * f..(c + 1) * f * f]).to_owned().into_shape((f, f)).unwrap()
+}
+
+fn conv3d(in_channel: usize, out_channel: usize, stride: usize, width: usize, filter: usize) -> Conv3D {
+    let conv = Conv3D::new(ConvConfig::new(in_channel, out_channel, stride, 1, width, filter), 0.1);
+    *conv.weights.write().unwrap() = matrix(out_channel, in_channel * filter * filter, 1);
+    *conv.bias.write().unwrap() = matrix(out_channel, 1, 2);
+    conv
+}
+
+#[test]
+fn conv_forward_without_dilation() {
+    // the only padding the first Conv2D supported is 1
+    for &(stride, width, filter) in &[(1, 6, 3), (2, 7, 3), (1, 5, 2), (2, 9, 4)] {
+        let conv = conv3d(2, 3, stride, width, filter);
+        let inputs = tensor(2, 2, width, 10);
+        let outputs = conv.forward(&inputs);
+        let bias = conv.bias.read().unwrap();
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            for out in 0..3 {
+                let expected = input.iter().enumerate().fold(Array2::from_elem(output[out].raw_dim(), bias[[out, 0]]), |acc, (c, arr)| {
+                    acc + convolution(&kernel(&conv, out, c), arr, stride, 1)
+                });
+                assert_close(&output[out], &expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn conv_backward_without_dilation() {
+    // stride 1, padding 1 and a 3x3 filter, where the rotated filter of the first Conv2D gives the right deltas
+    let width = 6;
+    let conv = conv3d(2, 3, 1, width, 3);
+    let inputs = tensor(2, 2, width, 20);
+    let next_deltas = tensor(2, 3, width, 30);
+    let (deltas, gradients) = conv.gradients(&inputs, next_deltas.clone());
+
+    for (delta, next_delta) in deltas.iter().zip(next_deltas.iter()) {
+        for c in 0..2 {
+            let expected = next_delta.iter().enumerate().fold(Array2::zeros((width, width)), |acc, (out, arr)| {
+                acc + convolution(&rotate(&kernel(&conv, out, c)), arr, 1, 1)
+            });
+            assert_close(&delta[c], &expected);
+        }
+    }
+
+    // the derivative of a filter is the convolution of the padded input with the deltas, summed over the samples
+    for out in 0..3 {
+        for c in 0..2 {
+            let expected = inputs.iter().zip(next_deltas.iter()).fold(Array2::zeros((3, 3)), |acc, (input, delta)| {
+                acc + convolution(&delta[out], &input[c], 1, 1)
+            });
+            let derivate = gradients.derivates[0].slice(s![out, c * 9..(c + 1) * 9]).to_owned().into_shape((3, 3)).unwrap();
+            assert_close(&derivate, &expected);
+        }
+    }
+}
+
+// the kernels before they worked on slices: im2col, col2im, max pooling and evaluate must give exactly the same results
+
+fn random(rows: usize, cols: usize, rng: &mut StdRng) -> Array2<f32> {
+    Array2::from_shape_fn((rows, cols), |_| rng.gen_range(-1., 1.))
+}
+
+fn padded(matrix: &Array2<f32>, padding: usize) -> Array2<f32> {
+    let width = matrix.shape()[0];
+    let mut padded = Array::zeros((width + 2 * padding, width + 2 * padding));
+    padded.slice_mut(s![padding..padding + width, padding..padding + width]).assign(matrix);
+    padded
+}
+
+fn windows(matrix: &Array2<f32>, width: usize, stride: usize, dilation: usize) -> Vec<Vec<f32>> {
+    let input_width = matrix.shape()[0];
+    let span = (width - 1) * dilation + 1;
+    let mut windows = vec![];
+    let mut x = 0;
+
+    while x < input_width - span + 1 {
+        let mut y = 0;
+        while y < input_width - span + 1 {
+            let mut window = vec![];
+            for i in 0..width {
+                for j in 0..width {
+                    window.push(matrix[[x + i * dilation, y + j * dilation]]);
+                }
+            }
+            windows.push(window);
+            y += stride;
+        }
+        x += stride;
+    }
+    windows
+}
+
+fn output_width(input_width: usize, width: usize, stride: usize, padding: usize, dilation: usize) -> usize {
+    (input_width + 2 * padding - ((width - 1) * dilation + 1)) / stride + 1
+}
+
+fn old_im2col(matrix: &Array2<f32>, width: usize, stride: usize, padding: usize, dilation: usize) -> Array2<f32> {
+    let shape = output_width(matrix.shape()[0], width, stride, padding, dilation);
+    let windows = windows(&padded(matrix, padding), width, stride, dilation).into_iter().flatten().collect::<Vec<f32>>();
+    Array2::from_shape_vec((shape * shape, width * width), windows).unwrap()
+}
+
+fn old_col2im(cols: &Array2<f32>, input_width: usize, width: usize, stride: usize, padding: usize, dilation: usize) -> Array2<f32> {
+    let shape = output_width(input_width, width, stride, padding, dilation);
+    let padded_width = input_width + 2 * padding;
+    let mut padded = Array2::zeros((padded_width, padded_width));
+
+    for (index, row) in cols.outer_iter().enumerate() {
+        let (x, y) = (index / shape * stride, index % shape * stride);
+        for i in 0..width {
+            for j in 0..width {
+                padded[[x + i * dilation, y + j * dilation]] += row[i * width + j];
+            }
+        }
+    }
+    padded.slice(s![padding..padding + input_width, padding..padding + input_width]).to_owned()
+}
+
+fn old_max_pool(input: &Array2<f32>, width: usize, stride: usize) -> (Array2<f32>, Vec<usize>) {
+    let input_width = input.shape()[0];
+    let shape = output_width(input_width, width, stride, 0, 1);
+    let (values, indices): (Vec<f32>, Vec<usize>) = windows(input, width, stride, 1).into_iter().enumerate().map(|(index, window)| {
+        let (pos, max) = window.into_iter().enumerate().fold((0, f32::MIN), |(max_pos, acc), (pos, x)| {
+            let max = acc.max(x);
+            if max == acc { (max_pos, acc) } else { (pos, max) }
+        });
+        let row = index / shape * stride + pos / width;
+        let col = index % shape * stride + pos % width;
+        (max, row * input_width + col)
+    }).unzip();
+    (Array2::from_shape_vec((shape, shape), values).unwrap(), indices)
+}
+
+fn old_evaluate(output: &Array2<f32>, labels: &Array2<f32>) -> f32 {
+    let argmax = |row: ArrayView1<f32>| {
+        let mut max = (0, 0.);
+        for (i, &ele) in row.iter().enumerate() {
+            if ele > max.1 {
+                max = (i, ele);
+            }
+        }
+        max.0
+    };
+    let predictions = output.map_axis(Axis(1), argmax);
+    let labels = labels.map_axis(Axis(1), argmax);
+    predictions.iter().zip(labels.iter()).filter(|(p, l)| p == l).count() as f32
+}
+
+// (input_width, filter_width, stride, padding, dilation), with odd widths, strides above 1 and padding
+const SHAPES: [(usize, usize, usize, usize, usize); 8] = [
+    (5, 3, 1, 0, 1), (7, 3, 2, 1, 1), (9, 2, 3, 2, 1), (8, 3, 1, 2, 2),
+    (11, 3, 2, 1, 2), (6, 1, 2, 0, 1), (13, 5, 3, 2, 1), (4, 4, 1, 3, 1)
+];
+
+#[test]
+fn im2col_matches_reference() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for &(width, filter, stride, padding, dilation) in &SHAPES {
+        let input = random(width, width, &mut rng);
+        assert_eq!(im2col(&input, filter, stride, padding, dilation), old_im2col(&input, filter, stride, padding, dilation));
+
+        // the rows of every channel, [channel * f * f, out * out]
+        let channels = vec![input.clone(), random(width, width, &mut rng)];
+        let expected = channels.iter().map(|c| old_im2col(c, filter, stride, padding, dilation).t().to_owned()).collect::<Vec<Array2<f32>>>();
+        let expected = stack(Axis(0), &expected.iter().map(|e| e.view()).collect::<Vec<_>>()).unwrap();
+        assert_eq!(_im2col_channels(&channels, filter, stride, padding, dilation), expected);
+    }
+}
+
+#[test]
+fn col2im_matches_reference() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for &(width, filter, stride, padding, dilation) in &SHAPES {
+        let shape = output_width(width, filter, stride, padding, dilation);
+        let cols = random(shape * shape, filter * filter, &mut rng);
+        assert_eq!(col2im(&cols, width, filter, stride, padding, dilation), old_col2im(&cols, width, filter, stride, padding, dilation));
+
+        let channels = random(2 * filter * filter, shape * shape, &mut rng);
+        let result = _col2im_channels(channels.view(), width, filter, stride, padding, dilation);
+        for (c, channel) in result.iter().enumerate() {
+            let col = channels.slice(s![c * filter * filter..(c + 1) * filter * filter, ..]).t().to_owned();
+            assert_eq!(*channel, old_col2im(&col, width, filter, stride, padding, dilation));
+        }
+    }
+}
+
+#[test]
+fn max_pool_matches_reference() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for &(width, filter, stride) in &[(4, 2, 2), (7, 2, 2), (9, 3, 2), (8, 3, 1), (11, 3, 3), (5, 5, 1)] {
+        // few distinct values, so the windows have ties and the first maximum must win
+        let input = Array2::from_shape_fn((width, width), |_| rng.gen_range(0, 4) as f32);
+        let (values, indices) = _max_pool(&input, filter, stride, 0, width);
+        let (expected_values, expected_indices) = old_max_pool(&input, filter, stride);
+        assert_eq!(values, expected_values);
+        assert_eq!(indices, expected_indices);
+        assert_eq!(_max_pool_values(&input, filter, stride, 0, width), expected_values);
+    }
+}
+
+#[test]
+fn evaluate_matches_reference() {
+    let mut rng = StdRng::seed_from_u64(4);
+    for &(samples, classes) in &[(1, 1), (7, 3), (33, 10)] {
+        let output = random(samples, classes, &mut rng);
+        let labels = Array2::from_shape_fn((samples, classes), |_| rng.gen_range(0, 2) as f32);
+        assert_eq!(evaluate(&output, &labels), old_evaluate(&output, &labels));
+
+        // the output of a full layer is column-major
+        let output = output.t().to_owned().reversed_axes();
+        assert_eq!(evaluate(&output, &labels), old_evaluate(&output, &labels));
+    }
+}
@@ -0,0 +1,212 @@
+// saved layers must load back, including the formats of older versions
+use ndarray::arr2;
+use ndarray::Array2;
+use utils::convolution::{Conv3D, ConvConfig};
+use utils::dataset::Normalize;
+use utils::propagation::Propagation;
+use utils::trained::convolution::{Conv3DJson, Conv3DParameters};
+use utils::network::{infer, load, nn, save, Checkpoint};
+use utils::random::set_seed;
+use utils::trained::{Convert, LoadError};
+use utils::trained::full_connected::FullJson;
+use utils::trained::graph::GraphJson;
+use utils::full_connected::FullLayer;
+use utils::graph::Graph;
+use utils::merge::Merge;
+use serde_json::json;
+
+#[test]
+fn conv_round_trip() {
+    let conv = Conv3D::new(ConvConfig::new(2, 4, 2, 1, 7, 3).dilation(2).groups(2), 0.1);
+    let (weights, bias) = (conv.weights.read().unwrap().clone(), conv.bias.read().unwrap().clone());
+
+    let json = Conv3DJson::new(conv).to_string();
+    let conv = serde_json::from_str::<Conv3DJson>(&json).unwrap().to_layer().unwrap();
+    assert_eq!((conv.dilation, conv.groups, conv.output_width), (2, 2, 3));
+    assert_eq!(*conv.weights.read().unwrap(), weights);
+    assert_eq!(*conv.bias.read().unwrap(), bias);
+}
+
+#[test]
+fn conv_legacy_format() {
+    // a Conv3D of 2 input and 2 output channels saved as a grid of Conv2D, with a 2x2 output and a bias per row
+    let json = legacy_json(0, [
+        ([1., 2., 3., 4.], vec![0.5, 1.5]),
+        ([5., 6., 7., 8.], vec![1.5, 0.5]),
+        ([-1., -2., -3., -4.], vec![0., 0.]),
+        ([0., 0., 0., 1.], vec![3., 3.])
+    ]);
+
+    let saved = serde_json::from_str::<Conv3DJson>(&json).unwrap();
+    assert!(matches!(saved.parameters, Conv3DParameters::Legacy { .. }));
+    let conv = saved.to_layer().unwrap();
+    assert_eq!((conv.dilation, conv.groups), (1, 1));
+    assert_eq!(*conv.weights.read().unwrap(), arr2(&[
+        [1., 2., 3., 4., 5., 6., 7., 8.],
+        [-1., -2., -3., -4., 0., 0., 0., 1.]
+    ]));
+    // the bias of every row, summed over the input channels
+    assert_eq!(*conv.bias.read().unwrap(), arr2(&[[2.], [3.]]));
+
+    // saved again in the current format
+    let json = Conv3DJson::new(conv).to_string();
+    assert!(json.contains("\"weights\"") && !json.contains("conv2d"));
+    let conv = serde_json::from_str::<Conv3DJson>(&json).unwrap().to_layer().unwrap();
+    assert_eq!(*conv.bias.read().unwrap(), arr2(&[[2.], [3.]]));
+}
+
+fn legacy_json(padding: usize, conv2d: [([f32; 4], Vec<f32>); 4]) -> String {
+    // Conv3D of 2 input and 2 output channels, 3x3 inputs and 2x2 filters, saved as a grid of Conv2D [out][in]
+    let output_width = 3 - 2 + 2 * padding + 1;
+    let conv2d = conv2d.iter().map(|(filter, bias)| format!(
+        r#"{{"prev":3,"filter_width":2,"filter":{:?},"bias":{:?},"stride":1,"padding":{},"alpha":0.1}}"#, filter, bias, padding
+    )).collect::<Vec<String>>();
+    format!(
+        r#"{{"in_channel":2,"out_channel":2,"stride":1,"padding":{},"prev_width":3,"output_width":{},"filter_width":2,"alpha":0.1,"boundary":0,"conv2d":[[{},{}],[{},{}]]}}"#,
+        padding, output_width, conv2d[0], conv2d[1], conv2d[2], conv2d[3]
+    )
+}
+
+#[test]
+fn conv_legacy_outputs() {
+    // the first Conv2D padded the input by 1 and added the bias of every output row
+    let filters = [[1., 2., 3., 4.], [5., 6., 7., 8.], [-1., -2., -3., -4.], [0.5, 0., 0., 1.]];
+    let biases = [vec![0.5, 1., 1.5, 2.], vec![1.5, 1., 0.5, 0.], vec![1., 1., 1., 1.], vec![-0.5, -0.5, -0.5, -0.5]];
+    let json = legacy_json(1, [
+        (filters[0], biases[0].clone()),
+        (filters[1], biases[1].clone()),
+        (filters[2], biases[2].clone()),
+        (filters[3], biases[3].clone())
+    ]);
+    let conv = serde_json::from_str::<Conv3DJson>(&json).unwrap().to_layer().unwrap();
+
+    let input = vec![vec![
+        Array2::from_shape_fn((3, 3), |(i, j)| (i * 3 + j) as f32 * 0.1),
+        Array2::from_shape_fn((3, 3), |(i, j)| 1. - (i + 2 * j) as f32 * 0.2)
+    ]];
+    let legacy = (0..2).map(|out| Array2::from_shape_fn((4, 4), |(r, c)| {
+        (0..2).map(|i| {
+            let pixel = |y: usize, x: usize| if y < 1 || x < 1 || y > 3 || x > 3 { 0. } else { input[0][i][[y - 1, x - 1]] };
+            let filter = filters[out * 2 + i];
+            filter[0] * pixel(r, c) + filter[1] * pixel(r, c + 1) + filter[2] * pixel(r + 1, c) + filter[3] * pixel(r + 1, c + 1)
+                + biases[out * 2 + i][r]
+        }).sum::<f32>()
+    })).collect::<Vec<Array2<f32>>>();
+
+    let output = conv.forward(&input);
+    for (result, expected) in output[0].iter().zip(legacy.iter()) {
+        assert!(result.iter().zip(expected.iter()).all(|(r, e)| (r - e).abs() <= 1e-5), "{} != {}", result, expected);
+    }
+}
+
+#[test]
+fn conv_legacy_row_biases_are_an_error() {
+    // the rows of output channel 0 have the biases 1.5 and 2.5, a Conv3D can not reproduce them
+    let json = legacy_json(0, [
+        ([1., 2., 3., 4.], vec![0.5, 1.5]),
+        ([5., 6., 7., 8.], vec![1., 1.]),
+        ([-1., -2., -3., -4.], vec![0., 0.]),
+        ([0., 0., 0., 1.], vec![3., 3.])
+    ]);
+    let error = serde_json::from_str::<Conv3DJson>(&json).unwrap().to_layer().err().unwrap();
+    assert!(matches!(error, LoadError::Legacy(_)));
+}
+
+#[test]
+fn dtype_mismatch_is_an_error() {
+    let json = Conv3DJson::new(Conv3D::<f64>::new(ConvConfig::new(1, 2, 1, 1, 5, 3), 0.1)).to_string();
+    let error = serde_json::from_str::<Conv3DJson<f32>>(&json).unwrap().to_layer().err().unwrap();
+    assert!(matches!(error, LoadError::Dtype { ref saved, expected: "f32" } if saved == "f64"));
+}
+
+#[test]
+fn checkpoint_round_trip() {
+    set_seed(5);
+    let mut checkpoint = Checkpoint::<f32>::new(|| vec![
+        nn::new("Conv".to_string(), vec![1, 2, 1, 1, 6, 3, 0], 0.1),
+        nn::new("Relu".to_string(), vec![0], 0.1),
+        nn::new("Pool".to_string(), vec![2, 2, 0, 2, 6, 1], 0.1),
+        nn::new("Full".to_string(), vec![3, 18, 2], 0.1),
+        nn::new("Softmax".to_string(), vec![1], 0.1)
+    ]);
+    // drawn after the layers were built, the checkpoint keeps the seed they were built with
+    set_seed(6);
+    assert_eq!(checkpoint.seed, Some(5));
+    checkpoint.normalize = Some(Normalize::new(vec![0.5], vec![0.25]));
+    let parameters = checkpoint.network.iter().map(|layer| layer.parameters()).collect::<Vec<_>>();
+
+    // saving twice overwrites the file
+    let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    save(Checkpoint::new(|| vec![nn::new("Relu".to_string(), vec![0], 0.1)]), path);
+    save(checkpoint, path);
+    let loaded = load::<f32>(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.seed, Some(5));
+    assert_eq!(loaded.normalize, Some(Normalize::new(vec![0.5], vec![0.25])));
+    assert_eq!(loaded.network.iter().map(|layer| layer.parameters()).collect::<Vec<_>>(), parameters);
+}
+
+#[test]
+fn checkpoint_normalizes_raw_inputs() {
+    let mut checkpoint = Checkpoint::<f32>::new(|| vec![
+        nn::new("Conv".to_string(), vec![1, 2, 1, 1, 6, 3, 0], 0.1),
+        nn::new("Relu".to_string(), vec![0], 0.1),
+        nn::new("Pool".to_string(), vec![2, 2, 0, 2, 6, 1], 0.1),
+        nn::new("Full".to_string(), vec![3, 18, 2], 0.1),
+        nn::new("Softmax".to_string(), vec![1], 0.1)
+    ]);
+    checkpoint.normalize = Some(Normalize::new(vec![100.], vec![50.]));
+    let raw = (0..4).map(|s| vec![Array2::from_shape_fn((6, 6), |(i, j)| (s * 36 + i * 6 + j) as f32)]).collect::<Vec<_>>();
+    let target = Array2::from_shape_fn((4, 3), |(s, c)| if s % 3 == c { 1. } else { 0. });
+
+    let output = checkpoint.infer(&raw);
+    let normalized = checkpoint.normalize.as_ref().unwrap().normalize(&raw);
+    assert_eq!(output, infer(&checkpoint.network, &normalized));
+    assert_ne!(output, infer(&checkpoint.network, &raw));
+    let accuracy = checkpoint.predict(&raw, &target);
+
+    let path = std::env::temp_dir().join(format!("normalized-checkpoint-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    save(checkpoint, path);
+    let loaded = load::<f32>(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.infer(&raw), output);
+    assert_eq!(loaded.predict(&raw, &target), accuracy);
+}
+
+#[test]
+fn wrong_parameter_size_is_an_error() {
+    let mut saved = FullJson::new(FullLayer::<f32>::new(3, 4, 0.1, 0));
+    saved.weights.pop();
+    assert!(matches!(saved.to_layer().err().unwrap(), LoadError::Shape(_)));
+}
+
+#[test]
+fn invalid_graphs_are_an_error() {
+    let mut graph = Graph::<f32>::new("input", "sum");
+    graph.add_layer("a", nn::new("Relu".to_string(), vec![0], 0.1), "input")
+        .add_layer("b", nn::new("Relu".to_string(), vec![0], 0.1), "a")
+        .add_merge("sum", Merge::Add, vec!["a", "b"]);
+    let saved = serde_json::to_value(GraphJson::new(graph)).unwrap();
+    let load = |edit: &dyn Fn(&mut serde_json::Value)| {
+        let mut json = saved.clone();
+        edit(&mut json);
+        serde_json::from_value::<GraphJson>(json).unwrap().to_layer()
+    };
+
+    assert!(load(&|_| ()).is_ok());
+    let edits: Vec<Box<dyn Fn(&mut serde_json::Value)>> = vec![
+        Box::new(|json| json["nodes"][2]["op"] = json!({ "Merge": "Subtract" })),
+        Box::new(|json| json["nodes"][0]["inputs"] = json!(["b"])),
+        Box::new(|json| json["nodes"][1]["name"] = json!("a")),
+        Box::new(|json| json["nodes"][2]["inputs"] = json!(["a", "missing"])),
+        Box::new(|json| json["nodes"][2]["inputs"] = json!(["a"])),
+        Box::new(|json| json["output"] = json!("missing"))
+    ];
+    for edit in edits.iter() {
+        assert!(matches!(load(&**edit).err().unwrap(), LoadError::Graph(_)));
+    }
+}